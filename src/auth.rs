@@ -0,0 +1,54 @@
+use crate::consts::*;
+use crate::subnegotiation::*;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum AuthResult {
+  // the user the remote end authenticated as, if the mechanism carries one
+  Accepted(Option<String>),
+  Rejected,
+}
+
+// An authentication mechanism. On the client start provides the data for the first IS and
+// receive handles each REPLY. On the server receive handles each IS. The returned data is sent
+// back to the remote end in the next REPLY or IS, and the exchange ends once a result is
+// returned.
+pub trait Authenticator {
+  fn auth_type(&self) -> AuthType;
+  fn start(&mut self) -> Vec<u8>;
+  fn receive(&mut self, data: &[u8]) -> (Option<Vec<u8>>, Option<AuthResult>);
+}
+
+// The NULL authentication type proves nothing, the client simply claims a user name. Only
+// useful for tests and trusted networks.
+#[derive(Debug, Default)]
+pub struct NullAuthenticator {
+  name: Option<String>,
+}
+
+impl NullAuthenticator {
+  // the server side, accepts whatever name the client claims
+  pub fn new() -> NullAuthenticator {
+    NullAuthenticator::default()
+  }
+
+  // the client side, claims to be the given user
+  pub fn with_name(name: &str) -> NullAuthenticator {
+    NullAuthenticator { name: Some(name.to_string()) }
+  }
+}
+
+impl Authenticator for NullAuthenticator {
+  fn auth_type(&self) -> AuthType {
+    AuthType::new(AUTH_NULL, AUTH_CLIENT_TO_SERVER | AUTH_HOW_ONE_WAY)
+  }
+
+  fn start(&mut self) -> Vec<u8> {
+    self.name.clone().unwrap_or_default().into_bytes()
+  }
+
+  fn receive(&mut self, data: &[u8]) -> (Option<Vec<u8>>, Option<AuthResult>) {
+    let name = String::from_utf8_lossy(data).to_string();
+    let name = if name.is_empty() { None } else { Some(name) };
+    (None, Some(AuthResult::Accepted(name)))
+  }
+}
@@ -0,0 +1,15 @@
+use std::time::Instant;
+
+// source of the current time, swapped out in tests to avoid real sleeps
+pub trait Clock {
+  fn now(&self) -> Instant;
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+  fn now(&self) -> Instant {
+    Instant::now()
+  }
+}
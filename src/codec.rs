@@ -1,25 +1,485 @@
-use tokio::codec::{ Decoder, Encoder };
+use tokio::codec::{ Decoder, Encoder, Framed };
 use bytes::{ BufMut, BytesMut };
 use crate::event::*;
 use crate::subnegotiation::*;
 use crate::error::*;
 use crate::consts::*;
+use crate::option::*;
+use crate::negotiation::*;
+use crate::terminal_type::*;
+use crate::slc::*;
+use crate::charset::*;
+use crate::auth::*;
+use crate::encrypt::*;
+use crate::tn3270e::*;
 use std::mem;
+use std::collections::VecDeque;
 
 pub struct TelnetCodec {
-  pub sga: bool,
   max_buffer_length: usize,
   buffer: Vec<u8>,
+  options: Option<OptionTable>,
+  replies: Vec<TelnetEvent>,
+  events: VecDeque<TelnetEvent>,
+  closing: bool,
+  terminal_types: Option<TerminalTypeCycle>,
+  environ_dialect: Option<EnvironDialect>,
+  mode: LinemodeMode,
+  slc: Option<SlcTable>,
+  forward_mask: Option<ForwardMask>,
+  charset: Option<String>,
+  encoding: TextEncoding,
+  authenticators: Vec<Box<dyn Authenticator + Send>>,
+  encrypt: Option<Box<dyn StreamCipher + Send>>,
+  decrypt: Option<Box<dyn StreamCipher + Send>>,
+  encrypting: bool,
+  decrypting: bool,
+  // decrypted input that hasn't been decoded yet
+  plain: BytesMut,
+  end_matched: usize,
+  tls_follows_sent: bool,
+  tls_ready: bool,
+  location: Option<String>,
+  records: bool,
+  supported_functions: Option<Vec<u8>>,
+  functions: Option<Vec<u8>>,
 }
 
 impl TelnetCodec {
   pub fn new(max_buffer_length: usize) -> TelnetCodec {
     TelnetCodec {
-      sga: false,
       max_buffer_length,
       buffer: Vec::new(),
+      options: None,
+      replies: Vec::new(),
+      events: VecDeque::new(),
+      closing: false,
+      terminal_types: None,
+      environ_dialect: None,
+      mode: LinemodeMode { edit: true, ..LinemodeMode::default() },
+      slc: None,
+      forward_mask: None,
+      charset: None,
+      encoding: TextEncoding::Utf8,
+      authenticators: Vec::new(),
+      encrypt: None,
+      decrypt: None,
+      encrypting: false,
+      decrypting: false,
+      plain: BytesMut::new(),
+      end_matched: 0,
+      tls_follows_sent: false,
+      tls_ready: false,
+      location: None,
+      records: false,
+      supported_functions: None,
+      functions: None,
     }
   }
+
+  // a codec that answers Do/Dont/Will/Wont itself using the given option table
+  pub fn with_options(max_buffer_length: usize, options: OptionTable) -> TelnetCodec {
+    let mut codec = TelnetCodec::new(max_buffer_length);
+    codec.options = Some(options);
+    codec
+  }
+
+  pub fn options(&self) -> Option<&OptionTable> {
+    self.options.as_ref()
+  }
+
+  pub fn options_mut(&mut self) -> Option<&mut OptionTable> {
+    self.options.as_mut()
+  }
+
+  pub fn enable_local(&mut self, option: TelnetOption) {
+    let request = match self.options.as_mut() {
+      Some(options) => options.enable_local(option),
+      None => Some(TelnetEvent::Will(option)),
+    };
+    self.replies.extend(request);
+  }
+
+  pub fn disable_local(&mut self, option: TelnetOption) {
+    let request = match self.options.as_mut() {
+      Some(options) => options.disable_local(option),
+      None => Some(TelnetEvent::Wont(option)),
+    };
+    self.replies.extend(request);
+  }
+
+  pub fn enable_remote(&mut self, option: TelnetOption) {
+    let request = match self.options.as_mut() {
+      Some(options) => options.enable_remote(option),
+      None => Some(TelnetEvent::Do(option)),
+    };
+    self.replies.extend(request);
+  }
+
+  pub fn disable_remote(&mut self, option: TelnetOption) {
+    let request = match self.options.as_mut() {
+      Some(options) => options.disable_remote(option),
+      None => Some(TelnetEvent::Dont(option)),
+    };
+    self.replies.extend(request);
+  }
+
+  // send DO TIMING-MARK, a TimingMark event with the token is decoded once it is answered
+  pub fn timing_mark(&mut self) -> Option<TimingMarkToken> {
+    let (token, request) = self.options.as_mut()?.timing_mark();
+    self.replies.push(request);
+    Some(token)
+  }
+
+  // RFC 727, tell the remote end we are about to log it out. Once the pending replies are
+  // written the connection should be closed.
+  pub fn logout(&mut self) {
+    self.enable_local(TelnetOption::Logout);
+    self.closing = true;
+  }
+
+  // ask the remote end to log us out
+  pub fn request_logout(&mut self) {
+    self.enable_remote(TelnetOption::Logout);
+  }
+
+  // true once we have agreed to log out, the caller should flush the pending replies and
+  // output, then close the connection
+  pub fn is_closing(&self) -> bool {
+    self.closing
+  }
+
+  // keep sending TERMINAL-TYPE SEND until the client repeats itself, then decode a single
+  // TerminalTypes event with the full list. The remote end must already perform TERMINAL-TYPE.
+  pub fn request_terminal_types(&mut self) {
+    self.terminal_types = Some(TerminalTypeCycle::new());
+    self.replies.push(TerminalTypeCycle::request());
+  }
+
+  // the VAR/VALUE codes the remote end used in its last ENVIRON IS or INFO, use it to encode
+  // ENVIRON SEND lists it will understand
+  pub fn environ_dialect(&self) -> Option<EnvironDialect> {
+    self.environ_dialect
+  }
+
+  // the LINEMODE MODE currently in effect. Lines are buffered until CR LF while edit is set,
+  // otherwise every character is decoded as it arrives.
+  pub fn mode(&self) -> LinemodeMode {
+    self.mode
+  }
+
+  pub fn set_mode(&mut self, mode: LinemodeMode) {
+    self.mode = LinemodeMode { ack: false, ..mode };
+  }
+
+  // the special characters agreed with LINEMODE SLC, None until the remote end sends its
+  // table or one is set. The erase character and erase line characters are applied to
+  // buffered lines.
+  pub fn slc(&self) -> Option<&SlcTable> {
+    self.slc.as_ref()
+  }
+
+  pub fn slc_mut(&mut self) -> Option<&mut SlcTable> {
+    self.slc.as_mut()
+  }
+
+  pub fn set_slc(&mut self, table: SlcTable) {
+    self.slc = Some(table);
+  }
+
+  // the characters the client was asked to forward buffered input on. Sending
+  // LINEMODE DO FORWARDMASK sets it and DONT FORWARDMASK clears it. A masked character
+  // ends a PartialLine event instead of waiting for CR LF.
+  pub fn forward_mask(&self) -> Option<&ForwardMask> {
+    self.forward_mask.as_ref()
+  }
+
+  pub fn set_forward_mask(&mut self, mask: Option<ForwardMask>) {
+    self.forward_mask = mask;
+  }
+
+  // the charset agreed with CHARSET ACCEPTED, in either direction. Message text is converted
+  // with it when it is UTF-8, ISO-8859-1, US-ASCII or the IBM037 EBCDIC, other charsets are
+  // left as UTF-8. Lines still end with an ASCII CR LF.
+  pub fn charset(&self) -> Option<&str> {
+    self.charset.as_deref()
+  }
+
+  pub fn set_charset(&mut self, charset: &str) {
+    self.encoding = TextEncoding::from_name(charset).unwrap_or(TextEncoding::Utf8);
+    self.charset = Some(charset.to_string());
+  }
+
+  // once a mechanism is added the codec runs AUTHENTICATION exchanges itself and decodes an
+  // Authentication event with the result
+  pub fn add_authenticator<A: Authenticator + Send + 'static>(&mut self, authenticator: A) {
+    self.authenticators.push(Box::new(authenticator));
+  }
+
+  // server side, offer every mechanism in the order they were added. The remote end must
+  // already perform AUTHENTICATION.
+  pub fn request_authentication(&mut self) {
+    let auth_types = self.authenticators.iter().map(|authenticator| authenticator.auth_type()).collect();
+    self.replies.push(TelnetEvent::Subnegotiation(SubnegotiationType::Authentication(Authentication::Send(auth_types))));
+  }
+
+  // the cipher for our output, used from the ENCRYPT START we encode until our ENCRYPT END
+  pub fn set_encrypt_cipher<C: StreamCipher + Send + 'static>(&mut self, cipher: C) {
+    self.encrypt = Some(Box::new(cipher));
+  }
+
+  // the cipher for the remote end's output, used from its ENCRYPT START until its ENCRYPT END
+  pub fn set_decrypt_cipher<C: StreamCipher + Send + 'static>(&mut self, cipher: C) {
+    self.decrypt = Some(Box::new(cipher));
+  }
+
+  pub fn is_encrypting(&self) -> bool {
+    self.encrypting
+  }
+
+  pub fn is_decrypting(&self) -> bool {
+    self.decrypting
+  }
+
+  // send START_TLS FOLLOWS, the remote end must already perform START_TLS. Once its FOLLOWS
+  // arrives the codec stops decoding and the connection is ready for the TLS handshake.
+  pub fn start_tls(&mut self) {
+    self.replies.push(TelnetEvent::Subnegotiation(SubnegotiationType::StartTlsFollows));
+  }
+
+  // true once both ends sent START_TLS FOLLOWS. The caller should write the pending replies,
+  // then take the transport apart with into_tls_parts.
+  pub fn is_tls_ready(&self) -> bool {
+    self.tls_ready
+  }
+
+  // the last location the remote end sent with SEND-LOCATION
+  pub fn location(&self) -> Option<&str> {
+    self.location.as_deref()
+  }
+
+  // while set the input is split into TN3270E records at IAC EOR instead of lines. It follows
+  // the TN3270E option when the codec has an option table.
+  pub fn is_tn3270e(&self) -> bool {
+    self.records
+  }
+
+  pub fn set_tn3270e(&mut self, records: bool) {
+    self.records = records;
+  }
+
+  // once set the codec answers TN3270E FUNCTIONS REQUEST itself, agreeing to the requested
+  // functions it supports
+  pub fn set_tn3270e_functions(&mut self, supported: Vec<u8>) {
+    self.supported_functions = Some(supported);
+  }
+
+  // the functions both ends agreed to with FUNCTIONS IS
+  pub fn tn3270e_functions(&self) -> Option<&[u8]> {
+    self.functions.as_deref()
+  }
+
+  // requests that went unanswered for longer than the option table's timeout
+  pub fn poll_timeouts(&mut self) -> Vec<TelnetEvent> {
+    match self.options.as_mut() {
+      Some(options) => {
+        let now = options.now();
+        options.poll_timeouts(now)
+      },
+      None => Vec::new(),
+    }
+  }
+
+  // the events the codec wants sent to the remote end, in order
+  pub fn take_replies(&mut self) -> Vec<TelnetEvent> {
+    mem::take(&mut self.replies)
+  }
+
+  // write every pending reply into the buffer
+  pub fn encode_replies(&mut self, buf: &mut BytesMut) -> Result<(), TelnetError> {
+    for reply in self.take_replies() {
+      self.encode(reply, buf)?;
+    }
+    Ok(())
+  }
+
+  // post process a decoded event, queueing any replies it requires. Returns None when the
+  // event was fully handled by the codec and should not reach the application.
+  fn process(&mut self, event: TelnetEvent) -> Option<TelnetEvent> {
+    if let TelnetEvent::Subnegotiation(SubnegotiationType::Environ(environ, dialect)) = &event {
+      match environ {
+        // an empty list says nothing about the codes
+        Environ::Is(variables) | Environ::Info(variables) if !variables.is_empty() => {
+          self.environ_dialect = Some(*dialect);
+        },
+        _ => {},
+      }
+    }
+    if let TelnetEvent::Subnegotiation(SubnegotiationType::TerminalType(TerminalType::Is(name))) = &event {
+      if let Some(cycle) = self.terminal_types.as_mut() {
+        match cycle.receive(name.clone()) {
+          Some(request) => {
+            self.replies.push(request);
+            return None;
+          },
+          None => {
+            let cycle = self.terminal_types.take()?;
+            return Some(TelnetEvent::TerminalTypes(cycle.into_names()));
+          },
+        }
+      }
+    }
+    if let TelnetEvent::Subnegotiation(SubnegotiationType::Linemode(Linemode::Mode(mode))) = &event {
+      // RFC 1184, a MODE with ACK confirms the mode we proposed, any other MODE is adopted and
+      // acknowledged with the same mask
+      if !mode.ack {
+        let ack = LinemodeMode { ack: true, ..*mode };
+        self.replies.push(TelnetEvent::Subnegotiation(SubnegotiationType::Linemode(Linemode::Mode(ack))));
+      }
+      self.set_mode(*mode);
+    }
+    if let TelnetEvent::Subnegotiation(SubnegotiationType::Linemode(Linemode::Slc(triplets))) = &event {
+      let replies = self.slc.get_or_insert_with(SlcTable::new).receive(triplets);
+      if !replies.is_empty() {
+        self.replies.push(TelnetEvent::Subnegotiation(SubnegotiationType::Linemode(Linemode::Slc(replies))));
+      }
+    }
+    if let TelnetEvent::Subnegotiation(SubnegotiationType::Linemode(Linemode::WontForwardMask)) = event {
+      // the client refused the mask
+      self.forward_mask = None;
+    }
+    if let TelnetEvent::Subnegotiation(SubnegotiationType::Charset(Charset::Accepted(charset))) = &event {
+      // the remote end accepted one of the charsets we requested
+      self.set_charset(charset);
+    }
+    if let TelnetEvent::Subnegotiation(SubnegotiationType::Authentication(authentication)) = &event {
+      if !self.authenticators.is_empty() && self.authenticate(authentication) {
+        return None;
+      }
+    }
+    if let TelnetEvent::Subnegotiation(SubnegotiationType::Encrypt(Encrypt::Start(_))) = event {
+      // the bytes after the remote end's START are encrypted
+      self.decrypting = self.decrypt.is_some();
+      self.end_matched = 0;
+    }
+    if let TelnetEvent::Subnegotiation(SubnegotiationType::SendLocation(location)) = &event {
+      self.location = Some(location.clone());
+    }
+    if let TelnetEvent::Subnegotiation(SubnegotiationType::StartTlsFollows) = event {
+      // answer the server's FOLLOWS, nothing after the remote end's FOLLOWS is telnet
+      if !self.tls_follows_sent {
+        self.replies.push(TelnetEvent::Subnegotiation(SubnegotiationType::StartTlsFollows));
+      }
+      self.tls_ready = true;
+    }
+    if let TelnetEvent::Subnegotiation(SubnegotiationType::Tn3270e(tn3270e)) = &event {
+      match tn3270e {
+        Tn3270e::FunctionsRequest(requested) => {
+          if let Some(supported) = self.supported_functions.as_ref() {
+            let reply = Tn3270e::answer_functions(requested, supported);
+            self.replies.push(TelnetEvent::Subnegotiation(SubnegotiationType::Tn3270e(reply)));
+          }
+        },
+        Tn3270e::FunctionsIs(functions) => self.functions = Some(functions.clone()),
+        _ => {},
+      }
+    }
+    if let TelnetEvent::Subnegotiation(SubnegotiationType::Status(Status::Send)) = event {
+      if let Some(options) = self.options.as_ref() {
+        if options.local_enabled(TelnetOption::Status) {
+          let status = Status::Is(options.status());
+          self.replies.push(TelnetEvent::Subnegotiation(SubnegotiationType::Status(status)));
+          return None;
+        }
+      }
+    }
+    // RFC 727, a DO LOGOUT always reaches the application, our WILL or WONT carries the decision
+    let logout = matches!(event, TelnetEvent::Do(TelnetOption::Logout));
+    let mut rejected = false;
+    if let Some(options) = self.options.as_mut() {
+      rejected = options.rejects(&event);
+      self.replies.extend(options.receive(&event));
+      while let Some(change) = options.poll_event() {
+        let logout = match change {
+          TelnetEvent::OptionEnabled { option: TelnetOption::Logout, side } => Some(side),
+          _ => None,
+        };
+        match change {
+          TelnetEvent::OptionEnabled { option: TelnetOption::Tn3270e, .. } => self.records = true,
+          TelnetEvent::OptionDisabled { option: TelnetOption::Tn3270e, .. } => self.records = false,
+          _ => {},
+        }
+        self.events.push_back(change);
+        match logout {
+          // we agreed to log the remote end out, or it confirmed our logout
+          Some(Side::Local) => self.closing = true,
+          // the remote end agreed to log us out
+          Some(Side::Remote) => self.events.push_back(TelnetEvent::Logout(Side::Remote)),
+          None => {},
+        }
+      }
+    }
+    if logout {
+      self.events.push_back(TelnetEvent::Logout(Side::Local));
+    }
+    if rejected {
+      return None;
+    }
+    Some(event)
+  }
+
+  // run one step of an AUTHENTICATION exchange, returns false for messages the application
+  // should handle
+  fn authenticate(&mut self, authentication: &Authentication) -> bool {
+    let authenticators = &mut self.authenticators;
+    let find = |auth_type: &AuthType| {
+      authenticators.iter().position(|authenticator| authenticator.auth_type() == *auth_type)
+    };
+    match authentication {
+      Authentication::Send(offered) => {
+        // answer with the first offered type we support, or NULL when there is none
+        let reply = match offered.iter().find_map(find) {
+          Some(index) => {
+            let authenticator = &mut self.authenticators[index];
+            Authentication::Is(authenticator.auth_type(), authenticator.start())
+          },
+          None => Authentication::Is(AuthType::new(AUTH_NULL, 0), Vec::new()),
+        };
+        self.replies.push(TelnetEvent::Subnegotiation(SubnegotiationType::Authentication(reply)));
+      },
+      Authentication::Is(auth_type, data) | Authentication::Reply(auth_type, data) => {
+        let auth_type = *auth_type;
+        let result = match find(&auth_type) {
+          Some(index) => {
+            let (data, result) = self.authenticators[index].receive(data);
+            if let Some(data) = data {
+              // the server replies to IS, the client answers REPLY with the next IS
+              let reply = match authentication {
+                Authentication::Is(_, _) => Authentication::Reply(auth_type, data),
+                _ => Authentication::Is(auth_type, data),
+              };
+              self.replies.push(TelnetEvent::Subnegotiation(SubnegotiationType::Authentication(reply)));
+            }
+            result
+          },
+          None => {
+            // a server must answer every IS. The Kerberos, SPX and SRP mechanisms all use 1 for
+            // REJECT, which tells the client to stop waiting.
+            if let Authentication::Is(_, _) = authentication {
+              let reply = Authentication::Reply(auth_type, vec![AUTH_REPLY_REJECT]);
+              self.replies.push(TelnetEvent::Subnegotiation(SubnegotiationType::Authentication(reply)));
+            }
+            Some(AuthResult::Rejected)
+          },
+        };
+        if let Some(result) = result {
+          self.events.push_back(TelnetEvent::Authentication { auth_type, result });
+        }
+      },
+      Authentication::Name(_) => return false,
+    }
+    true
+  }
 }
 
 impl Encoder for TelnetCodec {
@@ -27,6 +487,15 @@ impl Encoder for TelnetCodec {
   type Error = TelnetError;
 
   fn encode(&mut self, event: TelnetEvent, buf: &mut BytesMut) -> Result<(), Self::Error> {
+      let start = buf.len();
+      let encrypting = match &event {
+        TelnetEvent::Subnegotiation(SubnegotiationType::Encrypt(Encrypt::Start(_))) => Some(self.encrypt.is_some()),
+        TelnetEvent::Subnegotiation(SubnegotiationType::Encrypt(Encrypt::End)) => Some(false),
+        _ => None,
+      };
+      if let TelnetEvent::Subnegotiation(SubnegotiationType::StartTlsFollows) = event {
+        self.tls_follows_sent = true;
+      }
       match event {
         // basic commands are IAC (COMMAND) (OPT)
         TelnetEvent::Do(opt) => {
@@ -53,54 +522,23 @@ impl Encoder for TelnetCodec {
           buf.put(WONT);
           buf.put::<u8>(opt.into());
         },
-        TelnetEvent::Subnegotiation(subnegotation) => {
-          match subnegotation {
-            SubnegotiationType::NegotiateAboutWindowSize(width, height) => {
-              buf.reserve(9);
-              buf.put(IAC);
-              buf.put(SUBNEGOTIATION);
-              buf.put(NEGOTIATE_ABOUT_WINDOW_SIZE);
-              buf.put_u16_be(width);
-              buf.put_u16_be(height);
-              buf.put(IAC);
-              buf.put(SUBNEGOTIATION_END);
+        TelnetEvent::Subnegotiation(subnegotiation) => {
+          match &subnegotiation {
+            // EXOPL can only carry options 256 through 511
+            SubnegotiationType::ExtendedOptionsList(list) if list.option().code().is_none() => {
+              return Err(TelnetError::InvalidExtendedOption);
             },
-            SubnegotiationType::Other(opt, bytes) => {
-              let mut len = 5_usize + bytes.len();
-
-              // scan the bytes and add one for each escape sequence
-              for x in &bytes {
-                if *x == IAC { len += 1; }
-              }
-
-              // reserve the exact amount of space for the subnegotiation
-              buf.reserve(len);
-
-              // IAC SUB Opt
-              buf.put(IAC);
-              buf.put(SUBNEGOTIATION);
-              buf.put::<u8>(opt.into());
-
-              // write the bytes...
-              for x in &bytes {
-                if *x == IAC {
-                  // if 255, escape it
-                  buf.put(IAC);
-                  buf.put(IAC);
-                } else {
-                  buf.put(*x);
-                }
-              }
-
-              // IAC SUBEND
-              buf.put(IAC);
-              buf.put(SUBNEGOTIATION_END);
-            }
-          };
+            SubnegotiationType::Linemode(Linemode::DoForwardMask(mask)) => self.forward_mask = Some(*mask),
+            SubnegotiationType::Linemode(Linemode::DontForwardMask) => self.forward_mask = None,
+            SubnegotiationType::Charset(Charset::Accepted(charset)) => self.set_charset(charset),
+            SubnegotiationType::Tn3270e(Tn3270e::FunctionsIs(functions)) => self.functions = Some(functions.clone()),
+            _ => {},
+          }
+          put_subnegotiation(buf, subnegotiation.option().into(), &subnegotiation.payload());
         },
         TelnetEvent::Message(msg) => {
-          // message is utf8
-          let bytes = msg.into_bytes();
+          // message text is converted to the accepted charset
+          let bytes = self.encoding.encode(&msg);
 
           // reserve the proper bytelength
           let mut len = bytes.len();
@@ -133,37 +571,165 @@ impl Encoder for TelnetCodec {
             }
           }
         }
+        TelnetEvent::Tn3270eRecord(record) => put_record(buf, &record.bytes()),
         _ => {
           // Nops can happen, ignore them
         },
       }
 
+      // START itself goes out in the clear and END is the last encrypted message
+      if self.encrypting {
+        if let Some(cipher) = self.encrypt.as_mut() {
+          cipher.apply(&mut buf[start..]);
+        }
+      }
+      if let Some(encrypting) = encrypting {
+        self.encrypting = encrypting;
+      }
+
       Ok(())
   }
 }
 
+// split a framed connection after START_TLS FOLLOWS into the transport and the bytes already
+// read from it, which are the start of the TLS handshake. Pending output must be flushed
+// first. Decoding continues with a fresh TelnetCodec on the TLS stream.
+pub fn into_tls_parts<T>(framed: Framed<T, TelnetCodec>) -> (T, BytesMut) {
+  let parts = framed.into_parts();
+  (parts.io, parts.read_buf)
+}
+
+// IAC SB <option> <bytes> IAC SE, escaping any IAC in the bytes
+fn put_subnegotiation(buf: &mut BytesMut, option: u8, bytes: &[u8]) {
+  let mut len = 5_usize + bytes.len();
+
+  // scan the bytes and add one for each escape sequence
+  for x in bytes {
+    if *x == IAC { len += 1; }
+  }
+
+  // reserve the exact amount of space for the subnegotiation
+  buf.reserve(len);
+
+  // IAC SUB Opt
+  buf.put(IAC);
+  buf.put(SUBNEGOTIATION);
+  buf.put(option);
+
+  // write the bytes...
+  for x in bytes {
+    if *x == IAC {
+      // if 255, escape it
+      buf.put(IAC);
+      buf.put(IAC);
+    } else {
+      buf.put(*x);
+    }
+  }
+
+  // IAC SUBEND
+  buf.put(IAC);
+  buf.put(SUBNEGOTIATION_END);
+}
+
+// <bytes> IAC EOR, escaping any IAC in the bytes
+fn put_record(buf: &mut BytesMut, bytes: &[u8]) {
+  let escapes = bytes.iter().filter(|x| **x == IAC).count();
+  buf.reserve(bytes.len() + escapes + 2);
+
+  for x in bytes {
+    if *x == IAC {
+      buf.put(IAC);
+    }
+    buf.put(*x);
+  }
+
+  buf.put(IAC);
+  buf.put(END_OF_RECORD_MARK);
+}
+
 impl Decoder for TelnetCodec {
   type Item = TelnetEvent;
   type Error = TelnetError;
 
   fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+    loop {
+      // the rest of the input belongs to the TLS handshake
+      if self.tls_ready && self.events.is_empty() {
+        return Ok(None);
+      }
+      // events generated while processing earlier frames come first
+      if let Some(event) = self.events.pop_front() {
+        return Ok(Some(event));
+      }
+      self.decrypt_input(src);
+      let frame = if self.plain.is_empty() && !self.decrypting {
+        self.decode_frame(src)?
+      } else {
+        // decrypted bytes come before anything left in src
+        let mut plain = mem::replace(&mut self.plain, BytesMut::new());
+        let frame = self.decode_frame(&mut plain);
+        self.plain = plain;
+        frame?
+      };
+      match frame {
+        Some(event) => {
+          if let Some(event) = self.process(event) {
+            return Ok(Some(event));
+          }
+        },
+        None => return Ok(None),
+      }
+    }
+  }
+}
+
+impl TelnetCodec {
+  // move encrypted bytes from the front of src to the plaintext buffer, stopping right after
+  // the remote end's ENCRYPT END
+  fn decrypt_input(&mut self, src: &mut BytesMut) {
+    let cipher = match self.decrypt.as_mut() {
+      Some(cipher) if self.decrypting => cipher,
+      _ => return,
+    };
+    let mut len = 0;
+    while len < src.len() && self.decrypting {
+      cipher.apply(&mut src[len..len + 1]);
+      self.end_matched = match_encrypt_end(self.end_matched, src[len]);
+      if self.end_matched == ENCRYPT_END_SEQUENCE.len() {
+        self.decrypting = false;
+        self.end_matched = 0;
+      }
+      len += 1;
+    }
+    self.plain.extend_from_slice(&src.split_to(len));
+  }
+
+  // parse a single event off the front of the source buffer
+  fn decode_frame(&mut self, src: &mut BytesMut) -> Result<Option<TelnetEvent>, TelnetError> {
     let mut x = 0;
     let len = src.len();
     let mut buffer_len = self.buffer.len();
-    let max_buffer_length = self.max_buffer_length;
+    // records are binary, line editing and the line length cap don't apply to them
+    let records = self.records;
+    let max_buffer_length = if records { usize::MAX } else { self.max_buffer_length };
+    let slc = self.slc.as_ref().filter(|_| !records);
+    let erase_character = slc.and_then(|slc| slc.value(SlcFunction::Ec));
+    let erase_line = slc.and_then(|slc| slc.value(SlcFunction::El));
+    let forward_mask = self.forward_mask.filter(|_| !records).unwrap_or_default();
 
-    if self.sga {
+    if !self.mode.edit && !records {
       if self.buffer.len() > 0 {
         // truncate the buffer into a message and emit it
         let buffer = mem::replace(&mut self.buffer, Vec::new());
-        let result = String::from_utf8_lossy(&buffer[..]);
-        return Ok(Some(TelnetEvent::Message(result.to_string())));
+        let result = self.encoding.decode(&buffer[..]);
+        return Ok(Some(TelnetEvent::Message(result)));
       }
     }
 
     if len == 0 { return Ok(None); }
 
-    if self.sga {
+    if !self.mode.edit && !records {
       let mut byte = src[0];
 
       match byte {
@@ -177,6 +743,8 @@ impl Decoder for TelnetCodec {
           byte = src[x + 1];
 
           match byte {
+            // negotiation is parsed the same way in both modes
+            DO | DONT | WILL | WONT | SUBNEGOTIATION => {},
             IAC => {
               src.split_to(2);
               return Ok(Some(TelnetEvent::Character(IAC)));
@@ -203,13 +771,18 @@ impl Decoder for TelnetCodec {
     }
 
     loop {
-      if x >= len { return Ok(None); }
+      if x >= len {
+        // everything up to here is in the line buffer
+        src.split_to(len);
+        return Ok(None);
+      }
       let mut byte = src[x];
       match byte {
         // parse the IAC
         IAC => {
           // check the length first
           if x + 1 >= len {
+            src.split_to(x);
             return Ok(None);
           }
 
@@ -217,7 +790,8 @@ impl Decoder for TelnetCodec {
           byte = src[x + 1];
           match byte {
             ERASE_LINE => {
-              mem::replace(&mut self.buffer, Vec::new());
+              self.buffer.clear();
+              buffer_len = 0;
               x += 1;
             }
             ERASE_CHARACTER => {
@@ -236,6 +810,7 @@ impl Decoder for TelnetCodec {
             },
             DO => {
               if x + 2 >= len {
+                src.split_to(x);
                 return Ok(None);
               }
               // split the buffer at the current index.
@@ -245,6 +820,7 @@ impl Decoder for TelnetCodec {
             },
             DONT => {
               if x + 2 >= len {
+                src.split_to(x);
                 return Ok(None);
               }
               // split the buffer at the current index.
@@ -254,6 +830,7 @@ impl Decoder for TelnetCodec {
             },
             WILL => {
               if x + 2 >= len {
+                src.split_to(x);
                 return Ok(None);
               }
               // split the buffer at the current index.
@@ -263,6 +840,7 @@ impl Decoder for TelnetCodec {
             },
             WONT => {
               if x + 2 >= len {
+                src.split_to(x);
                 return Ok(None);
               }
               // split the buffer at the current index.
@@ -274,7 +852,7 @@ impl Decoder for TelnetCodec {
               // parse a subnegotiation
               if x + 2 >= len {
                 // io bytes may have been consumed at this point, so truncate them
-                src.split_to(x + 2);
+                src.split_to(x);
                 return Ok(None);
               }
               let start_index = x;
@@ -286,7 +864,7 @@ impl Decoder for TelnetCodec {
               // loop until IAC ENDSUB
               loop {
                 // we need more data to parse the subnegotation
-                if x > len {
+                if x >= len {
                   // if the subnegotiation parse is incomplete, trucate to the start of the sub
                   src.split_to(start_index);
                   return Ok(None);
@@ -294,8 +872,10 @@ impl Decoder for TelnetCodec {
                 byte = src[x];
                 match byte {
                   IAC => {
-                    if x + 1 > len {
-                      return Ok(None); // need to read the next byte
+                    if x + 1 >= len {
+                      // need to read the next byte
+                      src.split_to(start_index);
+                      return Ok(None);
                     }
 
                     // check the byte for subnegotiation end
@@ -307,25 +887,8 @@ impl Decoder for TelnetCodec {
                           // continue parsing even though the subnegotiation failed
                           return Err(TelnetError::InvalidSubnegotiationSequence);
                         } else {
-                          match option {
-                            NEGOTIATE_ABOUT_WINDOW_SIZE => {
-                              // Assert that there are 4 bytes in the vec
-                              match subvec.len() {
-                                4 => {
-                                  let result = SubnegotiationType::NegotiateAboutWindowSize(
-                                    ((subvec[0] as u16) << 8) | (subvec[1] as u16), // big endian width
-                                    ((subvec[2] as u16) << 8) | (subvec[3] as u16), // big endian height
-                                  );
-                                  return Ok(Some(TelnetEvent::Subnegotiation(result)));
-                                },
-                                _ => return Err(TelnetError::InvalidSubnegotiationSequence) // Invalid
-                              }
-                            },
-                            _ => {
-                              let result = SubnegotiationType::Other(option.into(), subvec);
-                              return Ok(Some(TelnetEvent::Subnegotiation(result)));
-                            }
-                          }
+                          let result = SubnegotiationType::parse(option, subvec)?;
+                          return Ok(Some(TelnetEvent::Subnegotiation(result)));
                         }
                       },
                       IAC => {
@@ -345,6 +908,12 @@ impl Decoder for TelnetCodec {
                 x += 1;
               }
             },
+            END_OF_RECORD_MARK if records => {
+              let record = mem::take(&mut self.buffer);
+              src.split_to(x + 2);
+              let record = Tn3270eRecord::parse(&record)?;
+              return Ok(Some(TelnetEvent::Tn3270eRecord(record)));
+            },
             NOP => {
               x += 1;
             },
@@ -353,16 +922,16 @@ impl Decoder for TelnetCodec {
             },
           }
         },
-        b'\n' => {
+        b'\n' if !records => {
           // newline hit, truncate the buffer and emit the bytes as a utf8 string
-          let mut buffer = mem::replace(&mut self.buffer, Vec::new());
           // it could have ended with crlf
-          if buffer.ends_with(&[b'\r']) {
+          if self.buffer.ends_with(&[b'\r']) {
+            let mut buffer = mem::take(&mut self.buffer);
             buffer.pop();
             src.split_to(x + 1);
 
-            let result = String::from_utf8_lossy(&buffer[..]);
-            return Ok(Some(TelnetEvent::Message(result.to_string())));
+            let result = self.encoding.decode(&buffer[..]);
+            return Ok(Some(TelnetEvent::Message(result)));
           }
 
           // default byte action:
@@ -372,6 +941,15 @@ impl Decoder for TelnetCodec {
             buffer_len += 1;
           }
         },
+        // the characters agreed with LINEMODE SLC edit the buffered line
+        _ if Some(byte) == erase_character => {
+          self.buffer.pop();
+          buffer_len = self.buffer.len();
+        },
+        _ if Some(byte) == erase_line => {
+          self.buffer.clear();
+          buffer_len = 0;
+        },
         _ => {
           // default byte action:
           // if the buffer has reached max buffer length, drop the byte
@@ -379,6 +957,14 @@ impl Decoder for TelnetCodec {
             self.buffer.push(byte);
             buffer_len += 1;
           }
+          if forward_mask.contains(byte) {
+            // the client forwarded its input early, emit what has been typed so far
+            let buffer = mem::take(&mut self.buffer);
+            src.split_to(x + 1);
+
+            let result = self.encoding.decode(&buffer[..]);
+            return Ok(Some(TelnetEvent::PartialLine(result)));
+          }
         }
       }
       x += 1;
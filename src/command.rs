@@ -2,6 +2,7 @@ use crate::consts::*;
 
 #[derive(PartialEq, Debug)]
 pub enum TelnetCommand {
+    EndOfRecord      , // RFC 885, marks the end of a record.
     SubnegotiationEnd, // End of subnegotiation parameters.
     NOP              , // No operation.
     DataMark         , // The data stream portion of a Synch. This should always be accompanied by a TCP Urgent notification.
@@ -24,6 +25,7 @@ pub enum TelnetCommand {
 impl From<u8> for TelnetCommand {
   fn from(byte: u8) -> TelnetCommand {
     match byte {
+      END_OF_RECORD_MARK => TelnetCommand::EndOfRecord      ,
       SUBNEGOTIATION_END => TelnetCommand::SubnegotiationEnd,
       NOP                => TelnetCommand::NOP              ,
       DATA_MARK          => TelnetCommand::DataMark         ,
@@ -48,6 +50,7 @@ impl From<u8> for TelnetCommand {
 impl Into<u8> for TelnetCommand {
   fn into(self) -> u8 {
     match self {
+      TelnetCommand::EndOfRecord       => END_OF_RECORD_MARK,
       TelnetCommand::SubnegotiationEnd => SUBNEGOTIATION_END,
       TelnetCommand::NOP               => NOP               ,
       TelnetCommand::DataMark          => DATA_MARK         ,
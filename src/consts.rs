@@ -1,3 +1,4 @@
+pub const END_OF_RECORD_MARK: u8 = 0xEF;
 pub const SUBNEGOTIATION_END: u8 = 0xF0;
 pub const NOP: u8                = 0xF1;
 pub const DATA_MARK: u8          = 0xF2;
@@ -52,4 +53,230 @@ pub const TERMINAL_SPEED: u8                     = 0x20;
 pub const REMOTE_FLOW_CONTROL: u8                = 0x21;
 pub const LINEMODE: u8                           = 0x22;
 pub const X_DISPLAY_LOCATION: u8                 = 0x23;
+pub const ENVIRON: u8                            = 0x24;
+pub const AUTHENTICATION: u8                     = 0x25;
+pub const ENCRYPT: u8                            = 0x26;
+pub const NEW_ENVIRON: u8                        = 0x27;
+pub const TN3270E: u8                            = 0x28;
+pub const CHARSET: u8                            = 0x2A;
+pub const COM_PORT_OPTION: u8                    = 0x2C;
+pub const START_TLS: u8                          = 0x2E;
 pub const EXTENDED_OPTIONS_LIST: u8              = 0xFF;
+
+
+pub const IS: u8                                 = 0x0 ;
+pub const SEND: u8                               = 0x1 ;
+pub const INFO: u8                               = 0x2 ;
+
+pub const ENVIRON_VAR: u8                        = 0x0 ;
+pub const ENVIRON_VALUE: u8                      = 0x1 ;
+pub const ENVIRON_ESC: u8                        = 0x2 ;
+pub const ENVIRON_USERVAR: u8                    = 0x3 ;
+
+pub const LINEMODE_MODE: u8                      = 0x1 ;
+pub const LINEMODE_FORWARDMASK: u8               = 0x2 ;
+pub const LINEMODE_SLC: u8                       = 0x3 ;
+
+pub const MODE_EDIT: u8                          = 0x1 ;
+pub const MODE_TRAPSIG: u8                       = 0x2 ;
+pub const MODE_ACK: u8                           = 0x4 ;
+pub const MODE_SOFT_TAB: u8                      = 0x8 ;
+pub const MODE_LIT_ECHO: u8                      = 0x10;
+
+pub const SLC_SYNCH: u8                          = 0x1 ;
+pub const SLC_BRK: u8                            = 0x2 ;
+pub const SLC_IP: u8                             = 0x3 ;
+pub const SLC_AO: u8                             = 0x4 ;
+pub const SLC_AYT: u8                            = 0x5 ;
+pub const SLC_EOR: u8                            = 0x6 ;
+pub const SLC_ABORT: u8                          = 0x7 ;
+pub const SLC_EOF: u8                            = 0x8 ;
+pub const SLC_SUSP: u8                           = 0x9 ;
+pub const SLC_EC: u8                             = 0xa ;
+pub const SLC_EL: u8                             = 0xb ;
+pub const SLC_EW: u8                             = 0xc ;
+pub const SLC_RP: u8                             = 0xd ;
+pub const SLC_LNEXT: u8                          = 0xe ;
+pub const SLC_XON: u8                            = 0xf ;
+pub const SLC_XOFF: u8                           = 0x10;
+pub const SLC_FORW1: u8                          = 0x11;
+pub const SLC_FORW2: u8                          = 0x12;
+pub const SLC_MCL: u8                            = 0x13;
+pub const SLC_MCR: u8                            = 0x14;
+pub const SLC_MCWL: u8                           = 0x15;
+pub const SLC_MCWR: u8                           = 0x16;
+pub const SLC_MCBOL: u8                          = 0x17;
+pub const SLC_MCEOL: u8                          = 0x18;
+pub const SLC_INSRT: u8                          = 0x19;
+pub const SLC_OVER: u8                           = 0x1a;
+pub const SLC_ECR: u8                            = 0x1b;
+pub const SLC_EWR: u8                            = 0x1c;
+pub const SLC_EBOL: u8                           = 0x1d;
+pub const SLC_EEOL: u8                           = 0x1e;
+
+pub const SLC_NOSUPPORT: u8                      = 0x0 ;
+pub const SLC_CANTCHANGE: u8                     = 0x1 ;
+pub const SLC_VALUE: u8                          = 0x2 ;
+pub const SLC_DEFAULT: u8                        = 0x3 ;
+pub const SLC_LEVELBITS: u8                      = 0x3 ;
+pub const SLC_FLUSHOUT: u8                       = 0x20;
+pub const SLC_FLUSHIN: u8                        = 0x40;
+pub const SLC_ACK: u8                            = 0x80;
+
+pub const CHARSET_REQUEST: u8                    = 0x1 ;
+pub const CHARSET_ACCEPTED: u8                   = 0x2 ;
+pub const CHARSET_REJECTED: u8                   = 0x3 ;
+pub const CHARSET_TTABLE_IS: u8                  = 0x4 ;
+pub const CHARSET_TTABLE_REJECTED: u8            = 0x5 ;
+pub const CHARSET_TTABLE_ACK: u8                 = 0x6 ;
+pub const CHARSET_TTABLE_NAK: u8                 = 0x7 ;
+
+pub const COM_PORT_SIGNATURE: u8                 = 0x0 ;
+pub const COM_PORT_SET_BAUDRATE: u8              = 0x1 ;
+pub const COM_PORT_SET_DATASIZE: u8              = 0x2 ;
+pub const COM_PORT_SET_PARITY: u8                = 0x3 ;
+pub const COM_PORT_SET_STOPSIZE: u8              = 0x4 ;
+pub const COM_PORT_SET_CONTROL: u8               = 0x5 ;
+pub const COM_PORT_NOTIFY_LINESTATE: u8          = 0x6 ;
+pub const COM_PORT_NOTIFY_MODEMSTATE: u8         = 0x7 ;
+pub const COM_PORT_FLOWCONTROL_SUSPEND: u8       = 0x8 ;
+pub const COM_PORT_FLOWCONTROL_RESUME: u8        = 0x9 ;
+pub const COM_PORT_SET_LINESTATE_MASK: u8        = 0xa ;
+pub const COM_PORT_SET_MODEMSTATE_MASK: u8       = 0xb ;
+pub const COM_PORT_PURGE_DATA: u8                = 0xc ;
+pub const COM_PORT_SERVER_OFFSET: u8             = 0x64;
+
+pub const AUTH_REPLY: u8                         = 0x2 ;
+pub const AUTH_NAME: u8                          = 0x3 ;
+
+pub const AUTH_NULL: u8                          = 0x0 ;
+pub const AUTH_KERBEROS_V4: u8                   = 0x1 ;
+pub const AUTH_KERBEROS_V5: u8                   = 0x2 ;
+pub const AUTH_SPX: u8                           = 0x3 ;
+pub const AUTH_SRP: u8                           = 0x5 ;
+pub const AUTH_RSA: u8                           = 0x6 ;
+pub const AUTH_SSL: u8                           = 0x7 ;
+
+pub const AUTH_WHO_MASK: u8                      = 0x1 ;
+pub const AUTH_CLIENT_TO_SERVER: u8              = 0x0 ;
+pub const AUTH_SERVER_TO_CLIENT: u8              = 0x1 ;
+pub const AUTH_HOW_MASK: u8                      = 0x2 ;
+pub const AUTH_HOW_ONE_WAY: u8                   = 0x0 ;
+pub const AUTH_HOW_MUTUAL: u8                    = 0x2 ;
+pub const AUTH_ENCRYPT_MASK: u8                  = 0x14;
+pub const AUTH_INI_CRED_FWD_MASK: u8             = 0x8 ;
+
+pub const AUTH_REPLY_REJECT: u8                  = 0x1 ;
+
+pub const ENCRYPT_SUPPORT: u8                    = 0x1 ;
+pub const ENCRYPT_REPLY: u8                      = 0x2 ;
+pub const ENCRYPT_START: u8                      = 0x3 ;
+pub const ENCRYPT_END: u8                        = 0x4 ;
+pub const ENCRYPT_REQUEST_START: u8              = 0x5 ;
+pub const ENCRYPT_REQUEST_END: u8                = 0x6 ;
+pub const ENCRYPT_ENC_KEYID: u8                  = 0x7 ;
+pub const ENCRYPT_DEC_KEYID: u8                  = 0x8 ;
+
+pub const ENCTYPE_NULL: u8                       = 0x0 ;
+pub const ENCTYPE_DES_CFB64: u8                  = 0x1 ;
+pub const ENCTYPE_DES_OFB64: u8                  = 0x2 ;
+pub const ENCTYPE_DES3_CFB64: u8                 = 0x3 ;
+pub const ENCTYPE_DES3_OFB64: u8                 = 0x4 ;
+pub const ENCTYPE_CAST5_40_CFB64: u8             = 0x8 ;
+pub const ENCTYPE_CAST5_40_OFB64: u8             = 0x9 ;
+pub const ENCTYPE_CAST128_CFB64: u8              = 0xa ;
+pub const ENCTYPE_CAST128_OFB64: u8              = 0xb ;
+
+pub const START_TLS_FOLLOWS: u8                  = 0x1 ;
+
+pub const TN3270E_ASSOCIATE: u8                  = 0x0 ;
+pub const TN3270E_CONNECT: u8                    = 0x1 ;
+pub const TN3270E_DEVICE_TYPE: u8                = 0x2 ;
+pub const TN3270E_FUNCTIONS: u8                  = 0x3 ;
+pub const TN3270E_IS: u8                         = 0x4 ;
+pub const TN3270E_REASON: u8                     = 0x5 ;
+pub const TN3270E_REJECT: u8                     = 0x6 ;
+pub const TN3270E_REQUEST: u8                    = 0x7 ;
+pub const TN3270E_SEND: u8                       = 0x8 ;
+
+pub const TN3270E_REASON_CONN_PARTNER: u8        = 0x0 ;
+pub const TN3270E_REASON_DEVICE_IN_USE: u8       = 0x1 ;
+pub const TN3270E_REASON_INV_ASSOCIATE: u8       = 0x2 ;
+pub const TN3270E_REASON_INV_NAME: u8            = 0x3 ;
+pub const TN3270E_REASON_INV_DEVICE_TYPE: u8     = 0x4 ;
+pub const TN3270E_REASON_TYPE_NAME_ERROR: u8     = 0x5 ;
+pub const TN3270E_REASON_UNKNOWN_ERROR: u8       = 0x6 ;
+pub const TN3270E_REASON_UNSUPPORTED_REQ: u8     = 0x7 ;
+
+pub const TN3270E_BIND_IMAGE: u8                 = 0x0 ;
+pub const TN3270E_DATA_STREAM_CTL: u8            = 0x1 ;
+pub const TN3270E_RESPONSES: u8                  = 0x2 ;
+pub const TN3270E_SCS_CTL_CODES: u8              = 0x3 ;
+pub const TN3270E_SYSREQ: u8                     = 0x4 ;
+
+pub const TN3270E_DT_3270_DATA: u8               = 0x0 ;
+pub const TN3270E_DT_SCS_DATA: u8                = 0x1 ;
+pub const TN3270E_DT_RESPONSE: u8                = 0x2 ;
+pub const TN3270E_DT_BIND_IMAGE: u8              = 0x3 ;
+pub const TN3270E_DT_UNBIND: u8                  = 0x4 ;
+pub const TN3270E_DT_NVT_DATA: u8                = 0x5 ;
+pub const TN3270E_DT_REQUEST: u8                 = 0x6 ;
+pub const TN3270E_DT_SSCP_LU_DATA: u8            = 0x7 ;
+pub const TN3270E_DT_PRINT_EOJ: u8               = 0x8 ;
+
+pub const TN3270E_RSF_NO_RESPONSE: u8            = 0x0 ;
+pub const TN3270E_RSF_ERROR_RESPONSE: u8         = 0x1 ;
+pub const TN3270E_RSF_ALWAYS_RESPONSE: u8        = 0x2 ;
+pub const TN3270E_RSF_POSITIVE_RESPONSE: u8      = 0x0 ;
+pub const TN3270E_RSF_NEGATIVE_RESPONSE: u8      = 0x1 ;
+pub const TN3270E_RQF_ERR_COND_CLEARED: u8       = 0x0 ;
+
+pub const CMD_WRITE: u8                          = 0xF1;
+pub const CMD_ERASE_WRITE: u8                    = 0xF5;
+pub const CMD_ERASE_WRITE_ALTERNATE: u8          = 0x7E;
+pub const CMD_WRITE_STRUCTURED_FIELD: u8         = 0xF3;
+pub const CMD_ERASE_ALL_UNPROTECTED: u8          = 0x6F;
+pub const CMD_READ_BUFFER: u8                    = 0xF2;
+pub const CMD_READ_MODIFIED: u8                  = 0xF6;
+pub const CMD_READ_MODIFIED_ALL: u8              = 0x6E;
+pub const CMD_SNA_WRITE: u8                      = 0x01;
+pub const CMD_SNA_ERASE_WRITE: u8                = 0x05;
+pub const CMD_SNA_ERASE_WRITE_ALTERNATE: u8      = 0x0D;
+pub const CMD_SNA_WRITE_STRUCTURED_FIELD: u8     = 0x11;
+pub const CMD_SNA_ERASE_ALL_UNPROTECTED: u8      = 0x0F;
+pub const CMD_SNA_READ_BUFFER: u8                = 0x02;
+pub const CMD_SNA_READ_MODIFIED: u8              = 0x06;
+pub const CMD_SNA_READ_MODIFIED_ALL: u8          = 0x0E;
+
+pub const ORDER_PT: u8                           = 0x05;
+pub const ORDER_GE: u8                           = 0x08;
+pub const ORDER_SBA: u8                          = 0x11;
+pub const ORDER_EUA: u8                          = 0x12;
+pub const ORDER_IC: u8                           = 0x13;
+pub const ORDER_SF: u8                           = 0x1D;
+pub const ORDER_SA: u8                           = 0x28;
+pub const ORDER_SFE: u8                          = 0x29;
+pub const ORDER_MF: u8                           = 0x2C;
+pub const ORDER_RA: u8                           = 0x3C;
+
+pub const WCC_RESET: u8                          = 0x40;
+pub const WCC_START_PRINTER: u8                  = 0x08;
+pub const WCC_SOUND_ALARM: u8                    = 0x04;
+pub const WCC_KEYBOARD_RESTORE: u8               = 0x02;
+pub const WCC_RESET_MDT: u8                      = 0x01;
+
+pub const FA_PROTECTED: u8                       = 0x20;
+pub const FA_NUMERIC: u8                         = 0x10;
+pub const FA_DISPLAY_MASK: u8                    = 0x0C;
+pub const FA_INTENSIFIED: u8                     = 0x08;
+pub const FA_NONDISPLAY: u8                      = 0x0C;
+pub const FA_MODIFIED: u8                        = 0x01;
+pub const XA_3270_FIELD_ATTRIBUTE: u8            = 0xC0;
+
+pub const AID_NO_AID: u8                         = 0x60;
+pub const AID_ENTER: u8                          = 0x7D;
+pub const AID_CLEAR: u8                          = 0x6D;
+pub const AID_SYSREQ: u8                         = 0xF0;
+pub const AID_PA1: u8                            = 0x6C;
+pub const AID_PA2: u8                            = 0x6E;
+pub const AID_PA3: u8                            = 0x6B;
@@ -0,0 +1,734 @@
+use crate::consts::*;
+use crate::error::*;
+use crate::charset::*;
+
+// 12 bit buffer addresses and inbound attributes put 6 bits in each byte, coded so every byte
+// is a printable EBCDIC character
+const ADDRESS_CODES: [u8; 64] = [
+  0x40, 0xc1, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0x4a, 0x4b, 0x4c, 0x4d, 0x4e, 0x4f,
+  0x50, 0xd1, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0x5a, 0x5b, 0x5c, 0x5d, 0x5e, 0x5f,
+  0x60, 0x61, 0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0x6a, 0x6b, 0x6c, 0x6d, 0x6e, 0x6f,
+  0xf0, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0x7a, 0x7b, 0x7c, 0x7d, 0x7e, 0x7f,
+];
+
+const MAX_SCREEN_SIZE: usize = 0x4000;
+
+// the AID for PF1 through PF24
+const PF_KEYS: [u8; 24] = [
+  0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0x7a, 0x7b, 0x7c,
+  0xc1, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0x4a, 0x4b, 0x4c,
+];
+
+// a buffer address in either the 12 bit or the 14 bit form
+pub fn decode_address(high: u8, low: u8) -> u16 {
+  if high & 0xc0 == 0 {
+    u16::from_be_bytes([high, low])
+  } else {
+    u16::from(high & 0x3f) << 6 | u16::from(low & 0x3f)
+  }
+}
+
+// the 12 bit form for addresses that fit it, which every screen up to 4096 characters uses
+pub fn encode_address(address: u16) -> [u8; 2] {
+  if address < 0x1000 {
+    [ADDRESS_CODES[usize::from(address >> 6)], ADDRESS_CODES[usize::from(address & 0x3f)]]
+  } else {
+    (address & 0x3fff).to_be_bytes()
+  }
+}
+
+// the write control character sent after each write command
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Wcc {
+  pub reset: bool,
+  pub start_printer: bool,
+  pub sound_alarm: bool,
+  pub keyboard_restore: bool,
+  pub reset_mdt: bool,
+}
+
+impl Wcc {
+  pub fn from_bits(bits: u8) -> Wcc {
+    Wcc {
+      reset: bits & WCC_RESET != 0,
+      start_printer: bits & WCC_START_PRINTER != 0,
+      sound_alarm: bits & WCC_SOUND_ALARM != 0,
+      keyboard_restore: bits & WCC_KEYBOARD_RESTORE != 0,
+      reset_mdt: bits & WCC_RESET_MDT != 0,
+    }
+  }
+
+  pub fn bits(&self) -> u8 {
+    let mut bits = 0;
+    if self.reset { bits |= WCC_RESET; }
+    if self.start_printer { bits |= WCC_START_PRINTER; }
+    if self.sound_alarm { bits |= WCC_SOUND_ALARM; }
+    if self.keyboard_restore { bits |= WCC_KEYBOARD_RESTORE; }
+    if self.reset_mdt { bits |= WCC_RESET_MDT; }
+    bits
+  }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Order {
+  SetBufferAddress(u16),
+  StartField(u8),
+  // attribute type and value pairs, type 0xC0 is the field attribute
+  StartFieldExtended(Vec<(u8, u8)>),
+  SetAttribute(u8, u8),
+  ModifyField(Vec<(u8, u8)>),
+  InsertCursor,
+  ProgramTab,
+  // the character follows a GE when the bool is set
+  RepeatToAddress(u16, u8, bool),
+  EraseUnprotectedToAddress(u16),
+  GraphicEscape(u8),
+  // EBCDIC characters written from the current buffer address
+  Data(Vec<u8>),
+}
+
+impl Order {
+  pub fn parse(bytes: &[u8]) -> Result<Vec<Order>, TelnetError> {
+    let mut orders = Vec::new();
+    let mut rest = bytes;
+    while let Some((code, tail)) = rest.split_first() {
+      let (order, tail) = match (*code, tail) {
+        (ORDER_SBA, [high, low, tail @ ..]) => (Order::SetBufferAddress(decode_address(*high, *low)), tail),
+        (ORDER_SF, [attribute, tail @ ..]) => (Order::StartField(*attribute), tail),
+        (ORDER_SFE, [count, tail @ ..]) => {
+          let (pairs, tail) = parse_pairs(*count, tail)?;
+          (Order::StartFieldExtended(pairs), tail)
+        },
+        (ORDER_MF, [count, tail @ ..]) => {
+          let (pairs, tail) = parse_pairs(*count, tail)?;
+          (Order::ModifyField(pairs), tail)
+        },
+        (ORDER_SA, [kind, value, tail @ ..]) => (Order::SetAttribute(*kind, *value), tail),
+        (ORDER_IC, tail) => (Order::InsertCursor, tail),
+        (ORDER_PT, tail) => (Order::ProgramTab, tail),
+        (ORDER_RA, [high, low, ORDER_GE, character, tail @ ..]) => {
+          (Order::RepeatToAddress(decode_address(*high, *low), *character, true), tail)
+        },
+        (ORDER_RA, [high, low, character, tail @ ..]) if *character != ORDER_GE => {
+          (Order::RepeatToAddress(decode_address(*high, *low), *character, false), tail)
+        },
+        (ORDER_EUA, [high, low, tail @ ..]) => (Order::EraseUnprotectedToAddress(decode_address(*high, *low)), tail),
+        (ORDER_GE, [character, tail @ ..]) => (Order::GraphicEscape(*character), tail),
+        // an order cut short
+        (code, _) if is_order(code) => return Err(TelnetError::InvalidDataStream),
+        _ => {
+          let len = rest.iter().position(|byte| is_order(*byte)).unwrap_or(rest.len());
+          (Order::Data(rest[..len].to_vec()), &rest[len..])
+        },
+      };
+      orders.push(order);
+      rest = tail;
+    }
+    Ok(orders)
+  }
+
+  pub fn bytes(&self) -> Vec<u8> {
+    match self {
+      Order::SetBufferAddress(address) => {
+        let [high, low] = encode_address(*address);
+        vec![ORDER_SBA, high, low]
+      },
+      Order::StartField(attribute) => vec![ORDER_SF, *attribute],
+      Order::StartFieldExtended(pairs) => put_pairs(ORDER_SFE, pairs),
+      Order::SetAttribute(kind, value) => vec![ORDER_SA, *kind, *value],
+      Order::ModifyField(pairs) => put_pairs(ORDER_MF, pairs),
+      Order::InsertCursor => vec![ORDER_IC],
+      Order::ProgramTab => vec![ORDER_PT],
+      Order::RepeatToAddress(address, character, false) => {
+        let [high, low] = encode_address(*address);
+        vec![ORDER_RA, high, low, *character]
+      },
+      Order::RepeatToAddress(address, character, true) => {
+        let [high, low] = encode_address(*address);
+        vec![ORDER_RA, high, low, ORDER_GE, *character]
+      },
+      Order::EraseUnprotectedToAddress(address) => {
+        let [high, low] = encode_address(*address);
+        vec![ORDER_EUA, high, low]
+      },
+      Order::GraphicEscape(character) => vec![ORDER_GE, *character],
+      Order::Data(data) => data.clone(),
+    }
+  }
+}
+
+fn is_order(byte: u8) -> bool {
+  matches!(byte, ORDER_PT | ORDER_GE | ORDER_SBA | ORDER_EUA | ORDER_IC | ORDER_SF | ORDER_SA | ORDER_SFE | ORDER_MF | ORDER_RA)
+}
+
+// attribute type and value
+type AttributePair = (u8, u8);
+
+fn parse_pairs(count: u8, bytes: &[u8]) -> Result<(Vec<AttributePair>, &[u8]), TelnetError> {
+  let len = usize::from(count) * 2;
+  if bytes.len() < len {
+    return Err(TelnetError::InvalidDataStream);
+  }
+  let pairs = bytes[..len].chunks_exact(2).map(|pair| (pair[0], pair[1])).collect();
+  Ok((pairs, &bytes[len..]))
+}
+
+fn put_pairs(order: u8, pairs: &[(u8, u8)]) -> Vec<u8> {
+  let mut bytes = vec![order, pairs.len() as u8];
+  for (kind, value) in pairs {
+    bytes.push(*kind);
+    bytes.push(*value);
+  }
+  bytes
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct StructuredField {
+  pub id: u8,
+  pub data: Vec<u8>,
+}
+
+impl StructuredField {
+  // each field starts with a 2 byte length that counts itself, 0 means the rest of the bytes
+  pub fn parse(bytes: &[u8]) -> Result<Vec<StructuredField>, TelnetError> {
+    let mut fields = Vec::new();
+    let mut rest = bytes;
+    while !rest.is_empty() {
+      let len = match rest {
+        [0, 0, _, ..] => rest.len(),
+        [high, low, _, ..] => usize::from(u16::from_be_bytes([*high, *low])),
+        _ => return Err(TelnetError::InvalidDataStream),
+      };
+      if len < 3 || len > rest.len() {
+        return Err(TelnetError::InvalidDataStream);
+      }
+      fields.push(StructuredField { id: rest[2], data: rest[3..len].to_vec() });
+      rest = &rest[len..];
+    }
+    Ok(fields)
+  }
+
+  pub fn bytes(&self) -> Vec<u8> {
+    let len = (self.data.len() + 3) as u16;
+    let mut bytes = len.to_be_bytes().to_vec();
+    bytes.push(self.id);
+    bytes.extend_from_slice(&self.data);
+    bytes
+  }
+}
+
+// an outbound 3270 data stream, as carried by a 3270-DATA record. Both the local and the SNA
+// command codes are accepted.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Command {
+  Write(Wcc, Vec<Order>),
+  EraseWrite(Wcc, Vec<Order>),
+  EraseWriteAlternate(Wcc, Vec<Order>),
+  WriteStructuredField(Vec<StructuredField>),
+  EraseAllUnprotected,
+  ReadBuffer,
+  ReadModified,
+  ReadModifiedAll,
+}
+
+impl Command {
+  pub fn parse(bytes: &[u8]) -> Result<Command, TelnetError> {
+    match bytes {
+      [CMD_WRITE, wcc, orders @ ..] | [CMD_SNA_WRITE, wcc, orders @ ..] => {
+        Ok(Command::Write(Wcc::from_bits(*wcc), Order::parse(orders)?))
+      },
+      [CMD_ERASE_WRITE, wcc, orders @ ..] | [CMD_SNA_ERASE_WRITE, wcc, orders @ ..] => {
+        Ok(Command::EraseWrite(Wcc::from_bits(*wcc), Order::parse(orders)?))
+      },
+      [CMD_ERASE_WRITE_ALTERNATE, wcc, orders @ ..] | [CMD_SNA_ERASE_WRITE_ALTERNATE, wcc, orders @ ..] => {
+        Ok(Command::EraseWriteAlternate(Wcc::from_bits(*wcc), Order::parse(orders)?))
+      },
+      [CMD_WRITE_STRUCTURED_FIELD, fields @ ..] | [CMD_SNA_WRITE_STRUCTURED_FIELD, fields @ ..] => {
+        Ok(Command::WriteStructuredField(StructuredField::parse(fields)?))
+      },
+      [CMD_ERASE_ALL_UNPROTECTED, ..] | [CMD_SNA_ERASE_ALL_UNPROTECTED, ..] => Ok(Command::EraseAllUnprotected),
+      [CMD_READ_BUFFER, ..] | [CMD_SNA_READ_BUFFER, ..] => Ok(Command::ReadBuffer),
+      [CMD_READ_MODIFIED, ..] | [CMD_SNA_READ_MODIFIED, ..] => Ok(Command::ReadModified),
+      [CMD_READ_MODIFIED_ALL, ..] | [CMD_SNA_READ_MODIFIED_ALL, ..] => Ok(Command::ReadModifiedAll),
+      _ => Err(TelnetError::InvalidDataStream),
+    }
+  }
+
+  pub fn bytes(&self) -> Vec<u8> {
+    match self {
+      Command::Write(wcc, orders) => put_orders(CMD_WRITE, wcc, orders),
+      Command::EraseWrite(wcc, orders) => put_orders(CMD_ERASE_WRITE, wcc, orders),
+      Command::EraseWriteAlternate(wcc, orders) => put_orders(CMD_ERASE_WRITE_ALTERNATE, wcc, orders),
+      Command::WriteStructuredField(fields) => {
+        let mut bytes = vec![CMD_WRITE_STRUCTURED_FIELD];
+        for field in fields {
+          bytes.extend_from_slice(&field.bytes());
+        }
+        bytes
+      },
+      Command::EraseAllUnprotected => vec![CMD_ERASE_ALL_UNPROTECTED],
+      Command::ReadBuffer => vec![CMD_READ_BUFFER],
+      Command::ReadModified => vec![CMD_READ_MODIFIED],
+      Command::ReadModifiedAll => vec![CMD_READ_MODIFIED_ALL],
+    }
+  }
+}
+
+fn put_orders(command: u8, wcc: &Wcc, orders: &[Order]) -> Vec<u8> {
+  let mut bytes = vec![command, wcc.bits()];
+  for order in orders {
+    bytes.extend_from_slice(&order.bytes());
+  }
+  bytes
+}
+
+// the attention identifier, the key that sent the screen to the host
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Aid {
+  NoAid,
+  Enter,
+  Clear,
+  SysReq,
+  // PA1 through PA3
+  Pa(u8),
+  // PF1 through PF24
+  Pf(u8),
+  Other(u8),
+}
+
+impl Aid {
+  // keys that only send the AID in answer to Read Modified
+  pub fn is_short_read(self) -> bool {
+    matches!(self, Aid::Clear | Aid::Pa(_))
+  }
+}
+
+impl From<u8> for Aid {
+  fn from(byte: u8) -> Aid {
+    match byte {
+      AID_NO_AID => Aid::NoAid,
+      AID_ENTER => Aid::Enter,
+      AID_CLEAR => Aid::Clear,
+      AID_SYSREQ => Aid::SysReq,
+      AID_PA1 => Aid::Pa(1),
+      AID_PA2 => Aid::Pa(2),
+      AID_PA3 => Aid::Pa(3),
+      _ => match PF_KEYS.iter().position(|key| *key == byte) {
+        Some(index) => Aid::Pf(index as u8 + 1),
+        None => Aid::Other(byte),
+      },
+    }
+  }
+}
+
+impl From<Aid> for u8 {
+  fn from(aid: Aid) -> u8 {
+    match aid {
+      Aid::NoAid => AID_NO_AID,
+      Aid::Enter => AID_ENTER,
+      Aid::Clear => AID_CLEAR,
+      Aid::SysReq => AID_SYSREQ,
+      Aid::Pa(1) => AID_PA1,
+      Aid::Pa(2) => AID_PA2,
+      Aid::Pa(3) => AID_PA3,
+      Aid::Pa(_) => AID_NO_AID,
+      Aid::Pf(key) => {
+        let index = usize::from(key).checked_sub(1);
+        index.and_then(|index| PF_KEYS.get(index)).cloned().unwrap_or(AID_NO_AID)
+      },
+      Aid::Other(byte) => byte,
+    }
+  }
+}
+
+// a field on a formatted screen. The attribute takes up the position before the first
+// character.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Field {
+  pub address: u16,
+  pub start: u16,
+  pub length: u16,
+  pub attribute: u8,
+}
+
+impl Field {
+  pub fn is_protected(&self) -> bool {
+    self.attribute & FA_PROTECTED != 0
+  }
+
+  pub fn is_numeric(&self) -> bool {
+    self.attribute & FA_NUMERIC != 0
+  }
+
+  pub fn is_intensified(&self) -> bool {
+    self.attribute & FA_DISPLAY_MASK == FA_INTENSIFIED
+  }
+
+  pub fn is_hidden(&self) -> bool {
+    self.attribute & FA_DISPLAY_MASK == FA_NONDISPLAY
+  }
+
+  pub fn is_modified(&self) -> bool {
+    self.attribute & FA_MODIFIED != 0
+  }
+}
+
+// the terminal side of a 3270 session. Outbound commands are applied to the screen, keys are
+// typed into its unprotected fields and AID keys build the inbound data stream for the host.
+// Erase/Write Alternate keeps the current size.
+pub struct Screen {
+  rows: u16,
+  columns: u16,
+  // EBCDIC characters, 0 is a null
+  buffer: Vec<u8>,
+  attributes: Vec<Option<u8>>,
+  cursor: u16,
+  aid: Aid,
+  keyboard_locked: bool,
+  alarm: bool,
+}
+
+impl Screen {
+  // 14 bit buffer addresses reach 0x4000 characters at most
+  pub fn new(rows: u16, columns: u16) -> Result<Screen, TelnetError> {
+    let size = usize::from(rows) * usize::from(columns);
+    if !(1..=MAX_SCREEN_SIZE).contains(&size) {
+      return Err(TelnetError::InvalidScreenSize);
+    }
+    Ok(Screen {
+      rows,
+      columns,
+      buffer: vec![0; size],
+      attributes: vec![None; size],
+      cursor: 0,
+      aid: Aid::NoAid,
+      keyboard_locked: false,
+      alarm: false,
+    })
+  }
+
+  pub fn rows(&self) -> u16 {
+    self.rows
+  }
+
+  pub fn columns(&self) -> u16 {
+    self.columns
+  }
+
+  pub fn cursor(&self) -> u16 {
+    self.cursor
+  }
+
+  pub fn set_cursor(&mut self, address: u16) {
+    self.cursor = self.wrap(address);
+  }
+
+  // locked from an AID key until the host writes with keyboard restore
+  pub fn is_keyboard_locked(&self) -> bool {
+    self.keyboard_locked
+  }
+
+  // true when the last write sounded the alarm
+  pub fn alarm(&self) -> bool {
+    self.alarm
+  }
+
+  // apply an outbound command, returning the inbound data stream for read commands.
+  // Structured fields are ignored.
+  pub fn apply(&mut self, command: &Command) -> Option<Vec<u8>> {
+    match command {
+      Command::Write(wcc, orders) => self.write(false, wcc, orders),
+      Command::EraseWrite(wcc, orders) | Command::EraseWriteAlternate(wcc, orders) => self.write(true, wcc, orders),
+      Command::WriteStructuredField(_) => {},
+      Command::EraseAllUnprotected => {
+        let fields = self.fields();
+        if fields.is_empty() {
+          // an unformatted screen is unprotected throughout
+          self.buffer.iter_mut().for_each(|byte| *byte = 0);
+        }
+        for field in fields.iter().filter(|field| !field.is_protected()) {
+          self.attributes[usize::from(field.address)] = Some(field.attribute & !FA_MODIFIED);
+          let mut address = field.start;
+          for _ in 0..field.length {
+            self.buffer[usize::from(address)] = 0;
+            address = self.next(address);
+          }
+        }
+        self.cursor = self.next_unprotected(0).unwrap_or(0);
+        self.restore_keyboard();
+      },
+      Command::ReadBuffer => return Some(self.read_buffer()),
+      Command::ReadModified => return Some(self.read_modified(false)),
+      Command::ReadModifiedAll => return Some(self.read_modified(true)),
+    }
+    None
+  }
+
+  // the fields in buffer order, empty on an unformatted screen
+  pub fn fields(&self) -> Vec<Field> {
+    let addresses: Vec<u16> = (0..self.size()).filter(|address| self.attributes[usize::from(*address)].is_some()).collect();
+    addresses.iter().enumerate().map(|(index, address)| {
+      let end = addresses[(index + 1) % addresses.len()];
+      let length = if end > *address { end - address - 1 } else { self.size() - address - 1 + end };
+      Field {
+        address: *address,
+        start: self.next(*address),
+        length,
+        attribute: self.attributes[usize::from(*address)].unwrap_or_default(),
+      }
+    }).collect()
+  }
+
+  // the field a buffer address belongs to
+  pub fn field_at(&self, address: u16) -> Option<Field> {
+    let field_address = self.field_address(self.wrap(address))?;
+    let start = self.next(field_address);
+    let length = (0..self.size())
+      .map(|offset| self.wrap(start + offset))
+      .position(|address| self.attributes[usize::from(address)].is_some())
+      .unwrap_or_default();
+    Some(Field {
+      address: field_address,
+      start,
+      length: length as u16,
+      attribute: self.attributes[usize::from(field_address)].unwrap_or_default(),
+    })
+  }
+
+  // the text of a field, nulls are left out
+  pub fn field_text(&self, field: &Field) -> String {
+    let bytes: Vec<u8> = self.field_bytes(field).into_iter().filter(|byte| *byte != 0).collect();
+    TextEncoding::Ebcdic037.decode(&bytes)
+  }
+
+  // one line of the screen, nulls and attributes show as spaces. Rows past the bottom wrap
+  // around like addresses do.
+  pub fn row_text(&self, row: u16) -> String {
+    let start = usize::from(row % self.rows) * usize::from(self.columns);
+    let bytes: Vec<u8> = (start..start + usize::from(self.columns)).map(|address| {
+      match (self.attributes[address], self.buffer[address]) {
+        (None, byte) if byte != 0 => byte,
+        _ => 0x40,
+      }
+    }).collect();
+    TextEncoding::Ebcdic037.decode(&bytes)
+  }
+
+  // every row joined with newlines
+  pub fn text(&self) -> String {
+    (0..self.rows).map(|row| self.row_text(row)).collect::<Vec<String>>().join("\n")
+  }
+
+  // move the cursor to the start of the next unprotected field
+  pub fn tab(&mut self) {
+    self.cursor = self.next_unprotected(self.cursor).unwrap_or(0);
+  }
+
+  // type at the cursor, moving to the next unprotected field when one fills up. Fails on a
+  // protected field or an attribute position.
+  pub fn type_text(&mut self, text: &str) -> Result<(), TelnetError> {
+    for byte in TextEncoding::Ebcdic037.encode(text) {
+      let address = self.cursor;
+      if self.attributes[usize::from(address)].is_some() {
+        return Err(TelnetError::ProtectedField);
+      }
+      let field = self.field_address(address);
+      if let Some(field) = field {
+        let attribute = self.attributes[usize::from(field)].unwrap_or_default();
+        if attribute & FA_PROTECTED != 0 {
+          return Err(TelnetError::ProtectedField);
+        }
+        self.attributes[usize::from(field)] = Some(attribute | FA_MODIFIED);
+      }
+      self.buffer[usize::from(address)] = byte;
+      self.cursor = self.next(address);
+      if field.is_some() && self.attributes[usize::from(self.cursor)].is_some() {
+        self.tab();
+      }
+    }
+    Ok(())
+  }
+
+  // press an AID key, returning the Read Modified data stream to send. The keyboard stays
+  // locked until the host restores it, and Clear also clears the screen.
+  pub fn press(&mut self, aid: Aid) -> Vec<u8> {
+    self.aid = aid;
+    self.keyboard_locked = true;
+    let bytes = self.read_modified(false);
+    if aid == Aid::Clear {
+      self.erase();
+    }
+    bytes
+  }
+
+  // the AID, the cursor and the modified fields, each after an SBA with its first position.
+  // Short read keys only send the AID unless all is set. An unformatted screen sends all of
+  // its text.
+  pub fn read_modified(&self, all: bool) -> Vec<u8> {
+    let mut bytes = vec![self.aid.into()];
+    if self.aid.is_short_read() && !all {
+      return bytes;
+    }
+    bytes.extend_from_slice(&encode_address(self.cursor));
+    let fields = self.fields();
+    if fields.is_empty() {
+      bytes.extend(self.buffer.iter().filter(|byte| **byte != 0));
+    }
+    for field in fields.iter().filter(|field| field.is_modified()) {
+      bytes.push(ORDER_SBA);
+      bytes.extend_from_slice(&encode_address(field.start));
+      bytes.extend(self.field_bytes(field).into_iter().filter(|byte| *byte != 0));
+    }
+    bytes
+  }
+
+  // the AID, the cursor and the whole buffer with an SF for each attribute
+  pub fn read_buffer(&self) -> Vec<u8> {
+    let mut bytes = vec![self.aid.into()];
+    bytes.extend_from_slice(&encode_address(self.cursor));
+    for (attribute, byte) in self.attributes.iter().zip(self.buffer.iter()) {
+      match attribute {
+        Some(attribute) => {
+          bytes.push(ORDER_SF);
+          bytes.push(ADDRESS_CODES[usize::from(attribute & 0x3f)]);
+        },
+        None => bytes.push(*byte),
+      }
+    }
+    bytes
+  }
+
+  fn write(&mut self, erase: bool, wcc: &Wcc, orders: &[Order]) {
+    if erase {
+      self.erase();
+    }
+    if wcc.reset_mdt {
+      for attribute in self.attributes.iter_mut().flatten() {
+        *attribute &= !FA_MODIFIED;
+      }
+    }
+    let mut address = self.cursor;
+    for order in orders {
+      match order {
+        Order::SetBufferAddress(to) => address = self.wrap(*to),
+        Order::StartField(attribute) => {
+          self.set_attribute(address, *attribute);
+          address = self.next(address);
+        },
+        Order::StartFieldExtended(pairs) => {
+          let attribute = field_attribute(pairs).unwrap_or_default();
+          self.set_attribute(address, attribute);
+          address = self.next(address);
+        },
+        Order::ModifyField(pairs) => {
+          if let (Some(_), Some(attribute)) = (self.attributes[usize::from(address)], field_attribute(pairs)) {
+            self.attributes[usize::from(address)] = Some(attribute);
+          }
+          address = self.next(address);
+        },
+        // character attributes don't change the text
+        Order::SetAttribute(_, _) => {},
+        Order::InsertCursor => self.cursor = address,
+        Order::ProgramTab => address = self.next_unprotected(address).unwrap_or(0),
+        Order::RepeatToAddress(to, character, _) => {
+          let to = self.wrap(*to);
+          loop {
+            self.put(address, *character);
+            address = self.next(address);
+            if address == to { break; }
+          }
+        },
+        Order::EraseUnprotectedToAddress(to) => {
+          let to = self.wrap(*to);
+          // follow the attributes along the way instead of looking up each position's field
+          let mut protected = self.field_address(address)
+            .and_then(|field| self.attributes[usize::from(field)])
+            .is_some_and(|attribute| attribute & FA_PROTECTED != 0);
+          loop {
+            match self.attributes[usize::from(address)] {
+              Some(attribute) => protected = attribute & FA_PROTECTED != 0,
+              None if !protected => self.buffer[usize::from(address)] = 0,
+              None => {},
+            }
+            address = self.next(address);
+            if address == to { break; }
+          }
+        },
+        Order::GraphicEscape(character) => {
+          self.put(address, *character);
+          address = self.next(address);
+        },
+        Order::Data(data) => {
+          for character in data {
+            self.put(address, *character);
+            address = self.next(address);
+          }
+        },
+      }
+    }
+    if wcc.keyboard_restore {
+      self.restore_keyboard();
+    }
+    self.alarm = wcc.sound_alarm;
+  }
+
+  fn erase(&mut self) {
+    self.buffer.iter_mut().for_each(|byte| *byte = 0);
+    self.attributes.iter_mut().for_each(|attribute| *attribute = None);
+    self.cursor = 0;
+  }
+
+  fn restore_keyboard(&mut self) {
+    self.keyboard_locked = false;
+    self.aid = Aid::NoAid;
+  }
+
+  fn put(&mut self, address: u16, character: u8) {
+    self.buffer[usize::from(address)] = character;
+    self.attributes[usize::from(address)] = None;
+  }
+
+  fn set_attribute(&mut self, address: u16, attribute: u8) {
+    self.buffer[usize::from(address)] = 0;
+    self.attributes[usize::from(address)] = Some(attribute);
+  }
+
+  // the first position of the next unprotected field after the address, wrapping around
+  fn next_unprotected(&self, address: u16) -> Option<u16> {
+    (0..self.size())
+      .map(|offset| self.wrap(address + offset))
+      .find(|address| match self.attributes[usize::from(*address)] {
+        Some(attribute) => attribute & FA_PROTECTED == 0,
+        None => false,
+      })
+      .map(|address| self.next(address))
+  }
+
+  // the attribute position of the field an address belongs to, walking back from the address.
+  // Before the first attribute is the end of the last field.
+  fn field_address(&self, address: u16) -> Option<u16> {
+    (0..self.size())
+      .map(|offset| self.wrap(address + self.size() - offset))
+      .find(|address| self.attributes[usize::from(*address)].is_some())
+  }
+
+  fn field_bytes(&self, field: &Field) -> Vec<u8> {
+    (0..field.length).map(|offset| self.buffer[usize::from(self.wrap(field.start + offset))]).collect()
+  }
+
+  fn size(&self) -> u16 {
+    self.buffer.len() as u16
+  }
+
+  fn wrap(&self, address: u16) -> u16 {
+    address % self.size()
+  }
+
+  fn next(&self, address: u16) -> u16 {
+    self.wrap(address + 1)
+  }
+}
+
+fn field_attribute(pairs: &[(u8, u8)]) -> Option<u8> {
+  pairs.iter().find(|(kind, _)| *kind == XA_3270_FIELD_ATTRIBUTE).map(|(_, value)| *value)
+}
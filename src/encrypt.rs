@@ -0,0 +1,21 @@
+use crate::consts::*;
+
+// A cipher for one direction of the connection, called with each run of bytes in the order
+// they are sent or received. Ciphers for the ENCRYPT option must not change the length of
+// the data.
+pub trait StreamCipher {
+  fn apply(&mut self, bytes: &mut [u8]);
+}
+
+// the other side's IAC SB ENCRYPT END IAC SE, which is the last thing it encrypts
+pub(crate) const ENCRYPT_END_SEQUENCE: [u8; 6] = [IAC, SUBNEGOTIATION, ENCRYPT, ENCRYPT_END, IAC, SUBNEGOTIATION_END];
+
+// advance a match of ENCRYPT_END_SEQUENCE by one decrypted byte
+pub(crate) fn match_encrypt_end(matched: usize, byte: u8) -> usize {
+  match byte {
+    _ if byte == ENCRYPT_END_SEQUENCE[matched] => matched + 1,
+    // IAC IAC is an escaped data byte and can't start the sequence
+    IAC if ENCRYPT_END_SEQUENCE[matched - 1] != IAC => 1,
+    _ => 0,
+  }
+}
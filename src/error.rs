@@ -2,6 +2,13 @@
 pub enum TelnetError {
   InvalidSubnegotiationSequence,
   InvalidIACSequence,
+  InvalidExtendedOption,
+  InvalidTerminalSpeed,
+  InvalidDisplayLocation,
+  InvalidRecordHeader,
+  InvalidDataStream,
+  ProtectedField,
+  InvalidScreenSize,
   IO(std::io::Error),
 }
 
@@ -16,6 +23,13 @@ impl PartialEq for TelnetError {
     match (&self, &other) {
       (TelnetError::IO(a), TelnetError::IO(b)) => a.kind() == b.kind(),
       (TelnetError::InvalidSubnegotiationSequence, TelnetError::InvalidSubnegotiationSequence) => true,
+      (TelnetError::InvalidExtendedOption, TelnetError::InvalidExtendedOption) => true,
+      (TelnetError::InvalidTerminalSpeed, TelnetError::InvalidTerminalSpeed) => true,
+      (TelnetError::InvalidDisplayLocation, TelnetError::InvalidDisplayLocation) => true,
+      (TelnetError::InvalidRecordHeader, TelnetError::InvalidRecordHeader) => true,
+      (TelnetError::InvalidDataStream, TelnetError::InvalidDataStream) => true,
+      (TelnetError::ProtectedField, TelnetError::ProtectedField) => true,
+      (TelnetError::InvalidScreenSize, TelnetError::InvalidScreenSize) => true,
       _ => false
     }
   }
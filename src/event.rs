@@ -1,6 +1,10 @@
 use crate::option::*;
 use crate::consts::*;
 use crate::subnegotiation::*;
+use crate::negotiation::*;
+use crate::auth::*;
+use crate::tn3270e::*;
+use std::time::Duration;
 
 #[derive(Debug,PartialEq)]
 pub enum TelnetEvent {
@@ -10,10 +14,19 @@ pub enum TelnetEvent {
   Wont(TelnetOption),
   Subnegotiation(SubnegotiationType),
   Message(String),
+  PartialLine(String),
   Character(u8),
   EraseCharacter,
   EraseLine,
   Nop,
+  OptionEnabled { option: TelnetOption, side: Side },
+  OptionDisabled { option: TelnetOption, side: Side },
+  NegotiationTimeout { option: TelnetOption, side: Side },
+  TimingMark { token: TimingMarkToken, elapsed: Duration },
+  Logout(Side),
+  TerminalTypes(Vec<String>),
+  Authentication { auth_type: AuthType, result: AuthResult },
+  Tn3270eRecord(Tn3270eRecord),
 }
 
 impl Into<u8> for TelnetEvent {
@@ -25,10 +38,20 @@ impl Into<u8> for TelnetEvent {
       TelnetEvent::Wont(_) => WONT,
       TelnetEvent::Subnegotiation(_) => SUBNEGOTIATION,
       TelnetEvent::Message(_) => 0x00,
+      TelnetEvent::PartialLine(_) => 0x00,
       TelnetEvent::Character(val) => val,
       TelnetEvent::EraseCharacter => ERASE_CHARACTER,
       TelnetEvent::EraseLine => ERASE_LINE,
       TelnetEvent::Nop => NOP,
+      // events the codec synthesizes have no command of their own
+      TelnetEvent::OptionEnabled { .. } => 0x00,
+      TelnetEvent::OptionDisabled { .. } => 0x00,
+      TelnetEvent::NegotiationTimeout { .. } => 0x00,
+      TelnetEvent::TimingMark { .. } => 0x00,
+      TelnetEvent::Logout(_) => 0x00,
+      TelnetEvent::TerminalTypes(_) => 0x00,
+      TelnetEvent::Authentication { .. } => 0x00,
+      TelnetEvent::Tn3270eRecord(_) => 0x00,
     }
   }
 }
@@ -5,6 +5,15 @@ pub mod codec;
 pub mod subnegotiation;
 pub mod error;
 pub mod consts;
+pub mod negotiation;
+pub mod clock;
+pub mod terminal_type;
+pub mod slc;
+pub mod charset;
+pub mod auth;
+pub mod encrypt;
+pub mod tn3270e;
+pub mod data_stream;
 
 #[cfg(test)]
 mod tests {
@@ -16,6 +25,46 @@ mod tests {
     use crate::subnegotiation::*;
     use crate::error::*;
     use crate::consts::*;
+    use crate::negotiation::*;
+    use crate::clock::*;
+    use crate::terminal_type::*;
+    use crate::slc::*;
+    use crate::charset::*;
+    use crate::auth::*;
+    use crate::encrypt::*;
+    use crate::tn3270e::*;
+    use crate::data_stream::*;
+    use std::sync::{ Arc, Mutex };
+    use std::time::{ Duration, Instant };
+
+    struct XorCipher(u8);
+
+    impl StreamCipher for XorCipher {
+        fn apply(&mut self, bytes: &mut [u8]) {
+            for byte in bytes.iter_mut() {
+                *byte ^= self.0;
+            }
+        }
+    }
+
+    #[derive(Clone)]
+    struct MockClock(Arc<Mutex<Instant>>);
+
+    impl MockClock {
+        fn new() -> MockClock {
+            MockClock(Arc::new(Mutex::new(Instant::now())))
+        }
+
+        fn advance(&self, duration: Duration) {
+            *self.0.lock().unwrap() += duration;
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> Instant {
+            *self.0.lock().unwrap()
+        }
+    }
 
     fn consume(codec: &mut TelnetCodec, bytes: &mut BytesMut) -> Vec<Result<Option<TelnetEvent>, TelnetError>> {
         let mut result = Vec::new();
@@ -93,8 +142,12 @@ mod tests {
         let mut codec = TelnetCodec::new(4096);
         for x in 0..=255 {
             let mut bytes = BytesMut::from(vec![IAC, DO, x]);
-            let result = consume(&mut codec, &mut bytes);
+            let mut result = consume(&mut codec, &mut bytes);
 
+            // DO LOGOUT is followed by its own event
+            if x == LOGOUT {
+                assert_eq!(result.pop(), Some(Ok(Some(TelnetEvent::Logout(Side::Local)))));
+            }
             assert_eq!(bytes.len(), 0_usize);
             assert_eq!(
                 result,
@@ -416,7 +469,7 @@ mod tests {
     }
 
     #[test]
-    fn message_decode_sga() {
+    fn message_decode_character_mode() {
         let mut codec = TelnetCodec::new(4096);
         let mut input = BytesMut::from(vec![
             b'a',
@@ -425,7 +478,7 @@ mod tests {
             IAC, IAC,
         ]);
 
-        codec.sga = true;
+        codec.set_mode(LinemodeMode::default());
         let result = consume(&mut codec, &mut input);
         assert_eq!(
             result,
@@ -439,7 +492,7 @@ mod tests {
     }
 
     #[test]
-    fn message_decode_sga_with_buffer() {
+    fn message_decode_character_mode_with_buffer() {
         let mut codec = TelnetCodec::new(4096);
         let mut input = BytesMut::from(
             Vec::from(vec![0x48, 0x65, 0x6c, 0x6c, 0x6f, 0x20, 0x77, 0x6f, 0x72, 0x6c, 0x64, 0x21])
@@ -455,7 +508,7 @@ mod tests {
             IAC, IAC,
         ]);
 
-        codec.sga = true;
+        codec.set_mode(LinemodeMode::default());
         let result = consume(&mut codec, &mut second_input);
 
         assert_eq!(
@@ -469,4 +522,2337 @@ mod tests {
             ],
         );
     }
+
+    #[test]
+    fn option_table_enable_remote() {
+        let mut table = OptionTable::new();
+
+        assert_eq!(
+            table.enable_remote(TelnetOption::NegotiateAboutWindowSize),
+            Some(TelnetEvent::Do(TelnetOption::NegotiateAboutWindowSize)),
+        );
+        assert_eq!(table.state(TelnetOption::NegotiateAboutWindowSize).him, QState::WantYes);
+
+        // asking twice does not send a second request
+        assert_eq!(table.enable_remote(TelnetOption::NegotiateAboutWindowSize), None);
+
+        // the acknowledgement is not answered
+        assert_eq!(table.receive(&TelnetEvent::Will(TelnetOption::NegotiateAboutWindowSize)), None);
+        assert!(table.remote_enabled(TelnetOption::NegotiateAboutWindowSize));
+        assert!(!table.local_enabled(TelnetOption::NegotiateAboutWindowSize));
+    }
+
+    #[test]
+    fn option_table_refuses_unsolicited() {
+        let mut table = OptionTable::new();
+
+        assert_eq!(
+            table.receive(&TelnetEvent::Do(TelnetOption::Echo)),
+            Some(TelnetEvent::Wont(TelnetOption::Echo)),
+        );
+        assert_eq!(
+            table.receive(&TelnetEvent::Will(TelnetOption::Echo)),
+            Some(TelnetEvent::Dont(TelnetOption::Echo)),
+        );
+
+        // disabling an option that is already off is never acknowledged
+        assert_eq!(table.receive(&TelnetEvent::Dont(TelnetOption::Echo)), None);
+        assert_eq!(table.receive(&TelnetEvent::Wont(TelnetOption::Echo)), None);
+        assert_eq!(table.state(TelnetOption::Echo), OptionState::default());
+    }
+
+    #[test]
+    fn option_table_remote_disables() {
+        let mut table = OptionTable::new();
+
+        table.enable_local(TelnetOption::Echo);
+        assert_eq!(table.receive(&TelnetEvent::Do(TelnetOption::Echo)), None);
+        assert!(table.local_enabled(TelnetOption::Echo));
+
+        assert_eq!(
+            table.receive(&TelnetEvent::Dont(TelnetOption::Echo)),
+            Some(TelnetEvent::Wont(TelnetOption::Echo)),
+        );
+        assert!(!table.local_enabled(TelnetOption::Echo));
+    }
+
+    #[test]
+    fn option_table_queue() {
+        let mut table = OptionTable::new();
+
+        table.enable_remote(TelnetOption::SuppressGoAhead);
+
+        // changing our mind while the request is in flight is queued
+        assert_eq!(table.disable_remote(TelnetOption::SuppressGoAhead), None);
+        assert_eq!(
+            table.state(TelnetOption::SuppressGoAhead).himq,
+            QueueState::Opposite,
+        );
+
+        // the queued request is sent once the first one is answered
+        assert_eq!(
+            table.receive(&TelnetEvent::Will(TelnetOption::SuppressGoAhead)),
+            Some(TelnetEvent::Dont(TelnetOption::SuppressGoAhead)),
+        );
+        assert_eq!(table.state(TelnetOption::SuppressGoAhead).him, QState::WantNo);
+        assert_eq!(table.state(TelnetOption::SuppressGoAhead).himq, QueueState::Empty);
+
+        assert_eq!(table.receive(&TelnetEvent::Wont(TelnetOption::SuppressGoAhead)), None);
+        assert_eq!(table.state(TelnetOption::SuppressGoAhead).him, QState::No);
+    }
+
+    #[test]
+    fn codec_negotiation_replies() {
+        let mut codec = TelnetCodec::with_options(4096, OptionTable::new());
+        let mut bytes = BytesMut::from(vec![
+            IAC, DO, ECHO,
+            IAC, WILL, TERMINAL_TYPE,
+        ]);
+        let result = consume(&mut codec, &mut bytes);
+
+        // refused requests are answered by the codec alone
+        assert_eq!(result, vec![]);
+
+        let mut output = BytesMut::new();
+        codec.encode_replies(&mut output).expect("Invalid encoding sequence");
+        assert_eq!(
+            output,
+            BytesMut::from(vec![
+                IAC, WONT, ECHO,
+                IAC, DONT, TERMINAL_TYPE,
+            ]),
+        );
+        assert_eq!(codec.take_replies(), vec![]);
+    }
+
+    #[test]
+    fn codec_negotiation_requests() {
+        let mut codec = TelnetCodec::with_options(4096, OptionTable::new());
+
+        codec.enable_remote(TelnetOption::NegotiateAboutWindowSize);
+        assert_eq!(
+            codec.take_replies(),
+            vec![TelnetEvent::Do(TelnetOption::NegotiateAboutWindowSize)],
+        );
+
+        let mut bytes = BytesMut::from(vec![IAC, WILL, NEGOTIATE_ABOUT_WINDOW_SIZE]);
+        consume(&mut codec, &mut bytes);
+
+        assert_eq!(codec.take_replies(), vec![]);
+        assert!(codec.options().unwrap().remote_enabled(TelnetOption::NegotiateAboutWindowSize));
+    }
+
+    #[test]
+    fn option_policy_accepts() {
+        let policy = OptionPolicy::new()
+            .local(TelnetOption::Echo)
+            .remote(TelnetOption::NegotiateAboutWindowSize);
+        let mut table = OptionTable::with_policy(policy);
+
+        assert_eq!(
+            table.receive(&TelnetEvent::Do(TelnetOption::Echo)),
+            Some(TelnetEvent::Will(TelnetOption::Echo)),
+        );
+        assert_eq!(
+            table.receive(&TelnetEvent::Will(TelnetOption::NegotiateAboutWindowSize)),
+            Some(TelnetEvent::Do(TelnetOption::NegotiateAboutWindowSize)),
+        );
+
+        // the policy is per side
+        assert_eq!(
+            table.receive(&TelnetEvent::Will(TelnetOption::Echo)),
+            Some(TelnetEvent::Dont(TelnetOption::Echo)),
+        );
+        assert!(table.local_enabled(TelnetOption::Echo));
+        assert!(!table.remote_enabled(TelnetOption::Echo));
+        assert!(table.remote_enabled(TelnetOption::NegotiateAboutWindowSize));
+    }
+
+    #[test]
+    fn codec_policy_hides_refused() {
+        let policy = OptionPolicy::new().remote(TelnetOption::NegotiateAboutWindowSize);
+        let mut codec = TelnetCodec::with_options(4096, OptionTable::with_policy(policy));
+        let mut bytes = BytesMut::from(vec![
+            IAC, DO, ECHO,
+            IAC, WILL, NEGOTIATE_ABOUT_WINDOW_SIZE,
+            IAC, WILL, TERMINAL_TYPE,
+            IAC, WONT, LINEMODE,
+        ]);
+        let result = consume(&mut codec, &mut bytes);
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Will(TelnetOption::NegotiateAboutWindowSize))),
+                Ok(Some(TelnetEvent::OptionEnabled {
+                    option: TelnetOption::NegotiateAboutWindowSize,
+                    side: Side::Remote,
+                })),
+            ],
+        );
+        assert_eq!(
+            codec.take_replies(),
+            vec![
+                TelnetEvent::Wont(TelnetOption::Echo),
+                TelnetEvent::Do(TelnetOption::NegotiateAboutWindowSize),
+                TelnetEvent::Dont(TelnetOption::TerminalType),
+            ],
+        );
+    }
+
+    #[test]
+    fn codec_policy_shows_requested() {
+        // options we asked for are surfaced even when the policy would refuse them
+        let mut codec = TelnetCodec::with_options(4096, OptionTable::new());
+        codec.enable_remote(TelnetOption::TerminalType);
+        codec.take_replies();
+
+        let mut bytes = BytesMut::from(vec![IAC, WONT, TERMINAL_TYPE]);
+        let result = consume(&mut codec, &mut bytes);
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Wont(TelnetOption::TerminalType))),
+            ],
+        );
+        assert_eq!(codec.take_replies(), vec![]);
+    }
+
+    #[test]
+    fn option_table_events() {
+        let mut table = OptionTable::new();
+
+        table.enable_remote(TelnetOption::TerminalType);
+        assert_eq!(table.poll_event(), None);
+
+        table.receive(&TelnetEvent::Will(TelnetOption::TerminalType));
+        assert_eq!(
+            table.poll_event(),
+            Some(TelnetEvent::OptionEnabled { option: TelnetOption::TerminalType, side: Side::Remote }),
+        );
+        assert_eq!(table.poll_event(), None);
+
+        // the option stays in effect until the remote end confirms
+        table.disable_remote(TelnetOption::TerminalType);
+        assert_eq!(table.poll_event(), None);
+
+        table.receive(&TelnetEvent::Wont(TelnetOption::TerminalType));
+        assert_eq!(
+            table.poll_event(),
+            Some(TelnetEvent::OptionDisabled { option: TelnetOption::TerminalType, side: Side::Remote }),
+        );
+
+        // a refused request never enables anything
+        table.enable_local(TelnetOption::Echo);
+        table.receive(&TelnetEvent::Dont(TelnetOption::Echo));
+        assert_eq!(table.poll_event(), None);
+    }
+
+    #[test]
+    fn codec_option_events() {
+        let policy = OptionPolicy::new().local(TelnetOption::Echo);
+        let mut codec = TelnetCodec::with_options(4096, OptionTable::with_policy(policy));
+        let mut bytes = BytesMut::from(vec![
+            IAC, DO, ECHO,
+            IAC, DO, ECHO,
+            IAC, DONT, ECHO,
+        ]);
+        let result = consume(&mut codec, &mut bytes);
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Do(TelnetOption::Echo))),
+                Ok(Some(TelnetEvent::OptionEnabled { option: TelnetOption::Echo, side: Side::Local })),
+                Ok(Some(TelnetEvent::Do(TelnetOption::Echo))),
+                Ok(Some(TelnetEvent::Dont(TelnetOption::Echo))),
+                Ok(Some(TelnetEvent::OptionDisabled { option: TelnetOption::Echo, side: Side::Local })),
+            ],
+        );
+        assert_eq!(
+            codec.take_replies(),
+            vec![
+                TelnetEvent::Will(TelnetOption::Echo),
+                TelnetEvent::Wont(TelnetOption::Echo),
+            ],
+        );
+    }
+
+    #[test]
+    fn option_table_timeouts() {
+        let clock = MockClock::new();
+        let mut table = OptionTable::new()
+            .timeout(Duration::from_secs(5))
+            .clock(clock.clone());
+
+        table.enable_remote(TelnetOption::TerminalType);
+        table.enable_remote(TelnetOption::NegotiateAboutWindowSize);
+
+        clock.advance(Duration::from_secs(4));
+        assert_eq!(table.poll_timeouts(clock.now()), vec![]);
+
+        // answered requests stop their timer
+        table.receive(&TelnetEvent::Will(TelnetOption::NegotiateAboutWindowSize));
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(
+            table.poll_timeouts(clock.now()),
+            vec![TelnetEvent::NegotiationTimeout { option: TelnetOption::TerminalType, side: Side::Remote }],
+        );
+
+        // each request is only reported once
+        assert_eq!(table.poll_timeouts(clock.now()), vec![]);
+    }
+
+    #[test]
+    fn option_table_without_timeout() {
+        let clock = MockClock::new();
+        let mut table = OptionTable::new().clock(clock.clone());
+
+        table.enable_local(TelnetOption::Echo);
+        clock.advance(Duration::from_secs(3600));
+        assert_eq!(table.poll_timeouts(clock.now()), vec![]);
+    }
+
+    #[test]
+    fn codec_timeouts() {
+        let clock = MockClock::new();
+        let table = OptionTable::new()
+            .timeout(Duration::from_secs(1))
+            .clock(clock.clone());
+        let mut codec = TelnetCodec::with_options(4096, table);
+
+        codec.enable_local(TelnetOption::Echo);
+        assert_eq!(codec.poll_timeouts(), vec![]);
+
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(
+            codec.poll_timeouts(),
+            vec![TelnetEvent::NegotiationTimeout { option: TelnetOption::Echo, side: Side::Local }],
+        );
+    }
+
+    #[test]
+    fn subnegotiation_status_send() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut bytes = BytesMut::from(vec![
+            IAC, SUBNEGOTIATION, STATUS, SEND,
+            IAC, SUBNEGOTIATION_END,
+        ]);
+        let result = consume(&mut codec, &mut bytes);
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Status(Status::Send)))),
+            ],
+        );
+    }
+
+    #[test]
+    fn subnegotiation_status_is() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut bytes = BytesMut::from(vec![
+            IAC, SUBNEGOTIATION, STATUS, IS,
+            WILL, ECHO,
+            DO, SUPPRESS_GO_AHEAD,
+            SUBNEGOTIATION, NEGOTIATE_ABOUT_WINDOW_SIZE, 0, SUBNEGOTIATION_END, SUBNEGOTIATION_END, 0, IAC, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION_END,
+        ]);
+        let result = consume(&mut codec, &mut bytes);
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Status(Status::Is(vec![
+                    TelnetEvent::Will(TelnetOption::Echo),
+                    TelnetEvent::Do(TelnetOption::SuppressGoAhead),
+                    TelnetEvent::Subnegotiation(SubnegotiationType::NegotiateAboutWindowSize(240, 255)),
+                ]))))),
+            ],
+        );
+    }
+
+    #[test]
+    fn subnegotiation_status_is_truncated() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut bytes = BytesMut::from(vec![
+            IAC, SUBNEGOTIATION, STATUS, IS,
+            WILL, ECHO,
+            SUBNEGOTIATION, NEGOTIATE_ABOUT_WINDOW_SIZE, 0, 80,
+            IAC, SUBNEGOTIATION_END,
+        ]);
+        let result = consume(&mut codec, &mut bytes);
+
+        assert_eq!(
+            result,
+            vec![
+                Err(TelnetError::InvalidSubnegotiationSequence),
+            ],
+        );
+    }
+
+    #[test]
+    fn subnegotiation_status_encode() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut output = BytesMut::new();
+        let message = TelnetEvent::Subnegotiation(SubnegotiationType::Status(Status::Is(vec![
+            TelnetEvent::Will(TelnetOption::Echo),
+            TelnetEvent::Subnegotiation(SubnegotiationType::NegotiateAboutWindowSize(255, 240)),
+        ])));
+
+        codec.encode(message, &mut output).expect("Invalid encoding sequence");
+
+        assert_eq!(
+            output,
+            BytesMut::from(vec![
+                IAC, SUBNEGOTIATION, STATUS, IS,
+                WILL, ECHO,
+                SUBNEGOTIATION, NEGOTIATE_ABOUT_WINDOW_SIZE, 0, IAC, IAC, 0, SUBNEGOTIATION_END, SUBNEGOTIATION_END, SUBNEGOTIATION_END,
+                IAC, SUBNEGOTIATION_END,
+            ]),
+        );
+    }
+
+    #[test]
+    fn codec_status_answers_send() {
+        let policy = OptionPolicy::new()
+            .local(TelnetOption::Status)
+            .local(TelnetOption::Echo)
+            .remote(TelnetOption::NegotiateAboutWindowSize);
+        let mut codec = TelnetCodec::with_options(4096, OptionTable::with_policy(policy));
+        let mut bytes = BytesMut::from(vec![
+            IAC, DO, STATUS,
+            IAC, DO, ECHO,
+            IAC, WILL, NEGOTIATE_ABOUT_WINDOW_SIZE,
+        ]);
+        consume(&mut codec, &mut bytes);
+        codec.take_replies();
+
+        let mut bytes = BytesMut::from(vec![
+            IAC, SUBNEGOTIATION, STATUS, SEND,
+            IAC, SUBNEGOTIATION_END,
+        ]);
+        let result = consume(&mut codec, &mut bytes);
+
+        assert_eq!(result, vec![]);
+        assert_eq!(
+            codec.take_replies(),
+            vec![
+                TelnetEvent::Subnegotiation(SubnegotiationType::Status(Status::Is(vec![
+                    TelnetEvent::Will(TelnetOption::Echo),
+                    TelnetEvent::Will(TelnetOption::Status),
+                    TelnetEvent::Do(TelnetOption::NegotiateAboutWindowSize),
+                ]))),
+            ],
+        );
+    }
+
+    #[test]
+    fn option_table_timing_mark() {
+        let clock = MockClock::new();
+        let mut table = OptionTable::new().clock(clock.clone());
+
+        let (first, request) = table.timing_mark();
+        assert_eq!(request, TelnetEvent::Do(TelnetOption::TimingMark));
+        let (second, _) = table.timing_mark();
+        assert!(first != second);
+
+        clock.advance(Duration::from_millis(40));
+        assert_eq!(table.receive(&TelnetEvent::Will(TelnetOption::TimingMark)), None);
+        clock.advance(Duration::from_millis(10));
+        assert_eq!(table.receive(&TelnetEvent::Wont(TelnetOption::TimingMark)), None);
+
+        // marks are answered in order
+        assert_eq!(
+            table.poll_event(),
+            Some(TelnetEvent::TimingMark { token: first, elapsed: Duration::from_millis(40) }),
+        );
+        assert_eq!(
+            table.poll_event(),
+            Some(TelnetEvent::TimingMark { token: second, elapsed: Duration::from_millis(50) }),
+        );
+
+        // the option itself never comes into effect
+        assert!(!table.remote_enabled(TelnetOption::TimingMark));
+        assert_eq!(
+            table.receive(&TelnetEvent::Will(TelnetOption::TimingMark)),
+            Some(TelnetEvent::Dont(TelnetOption::TimingMark)),
+        );
+    }
+
+    #[test]
+    fn codec_timing_mark() {
+        let clock = MockClock::new();
+        let mut codec = TelnetCodec::with_options(4096, OptionTable::new().clock(clock.clone()));
+
+        let token = codec.timing_mark().expect("timing marks need an option table");
+        assert_eq!(codec.take_replies(), vec![TelnetEvent::Do(TelnetOption::TimingMark)]);
+
+        clock.advance(Duration::from_millis(120));
+        let mut bytes = BytesMut::from(vec![
+            b'h', b'i', b'\r', b'\n',
+            IAC, WILL, TIMING_MARK,
+        ]);
+        let result = consume(&mut codec, &mut bytes);
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Message(String::from("hi")))),
+                Ok(Some(TelnetEvent::TimingMark { token, elapsed: Duration::from_millis(120) })),
+            ],
+        );
+        assert_eq!(codec.take_replies(), vec![]);
+    }
+
+    #[test]
+    fn codec_answers_timing_mark() {
+        let policy = OptionPolicy::new().local(TelnetOption::TimingMark);
+        let mut codec = TelnetCodec::with_options(4096, OptionTable::with_policy(policy));
+        let mut bytes = BytesMut::from(vec![
+            IAC, DO, TIMING_MARK,
+            IAC, DO, TIMING_MARK,
+        ]);
+        let result = consume(&mut codec, &mut bytes);
+
+        // every mark is answered, not just the first
+        assert_eq!(result, vec![]);
+        assert_eq!(
+            codec.take_replies(),
+            vec![
+                TelnetEvent::Will(TelnetOption::TimingMark),
+                TelnetEvent::Will(TelnetOption::TimingMark),
+            ],
+        );
+    }
+
+    #[test]
+    fn subnegotiation_extended_options_list() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut bytes = BytesMut::from(vec![
+            IAC, SUBNEGOTIATION, EXTENDED_OPTIONS_LIST, DO, 0,
+            IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, EXTENDED_OPTIONS_LIST, WONT, IAC, IAC,
+            IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, EXTENDED_OPTIONS_LIST, WILL,
+            IAC, SUBNEGOTIATION_END,
+        ]);
+        let result = consume(&mut codec, &mut bytes);
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::ExtendedOptionsList(
+                    ExtendedOptionsList::Do(ExtendedOption(256)),
+                )))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::ExtendedOptionsList(
+                    ExtendedOptionsList::Wont(ExtendedOption(511)),
+                )))),
+                Err(TelnetError::InvalidSubnegotiationSequence),
+            ],
+        );
+    }
+
+    #[test]
+    fn subnegotiation_extended_options_list_encode() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut output = BytesMut::new();
+        let message = TelnetEvent::Subnegotiation(SubnegotiationType::ExtendedOptionsList(
+            ExtendedOptionsList::Will(ExtendedOption(300)),
+        ));
+
+        codec.encode(message, &mut output).expect("Invalid encoding sequence");
+
+        assert_eq!(
+            output,
+            BytesMut::from(vec![
+                IAC, SUBNEGOTIATION, EXTENDED_OPTIONS_LIST, WILL, 44,
+                IAC, SUBNEGOTIATION_END,
+            ]),
+        );
+
+        // option numbers below 256 are negotiated directly, and above 511 cannot be sent
+        for option in &[255, 512] {
+            let message = TelnetEvent::Subnegotiation(SubnegotiationType::ExtendedOptionsList(
+                ExtendedOptionsList::Do(ExtendedOption(*option)),
+            ));
+            assert_eq!(
+                codec.encode(message, &mut output),
+                Err(TelnetError::InvalidExtendedOption),
+            );
+        }
+    }
+
+    #[test]
+    fn codec_logout_requested() {
+        let policy = OptionPolicy::new().local(TelnetOption::Logout);
+        let mut codec = TelnetCodec::with_options(4096, OptionTable::with_policy(policy));
+        let mut bytes = BytesMut::from(vec![IAC, DO, LOGOUT]);
+        let result = consume(&mut codec, &mut bytes);
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Do(TelnetOption::Logout))),
+                Ok(Some(TelnetEvent::OptionEnabled { option: TelnetOption::Logout, side: Side::Local })),
+                Ok(Some(TelnetEvent::Logout(Side::Local))),
+            ],
+        );
+        assert!(codec.is_closing());
+
+        let mut output = BytesMut::new();
+        codec.encode_replies(&mut output).expect("Invalid encoding sequence");
+        assert_eq!(output, BytesMut::from(vec![IAC, WILL, LOGOUT]));
+    }
+
+    #[test]
+    fn codec_logout_refused() {
+        let mut codec = TelnetCodec::with_options(4096, OptionTable::new());
+        let mut bytes = BytesMut::from(vec![IAC, DO, LOGOUT]);
+        let result = consume(&mut codec, &mut bytes);
+
+        // the application still hears about the request, the WONT refuses it
+        assert_eq!(result, vec![Ok(Some(TelnetEvent::Logout(Side::Local)))]);
+        assert!(!codec.is_closing());
+        assert_eq!(codec.take_replies(), vec![TelnetEvent::Wont(TelnetOption::Logout)]);
+
+        // without an option table the application answers the DO itself
+        let mut codec = TelnetCodec::new(4096);
+        let mut bytes = BytesMut::from(vec![IAC, DO, LOGOUT]);
+        let result = consume(&mut codec, &mut bytes);
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Do(TelnetOption::Logout))),
+                Ok(Some(TelnetEvent::Logout(Side::Local))),
+            ],
+        );
+        assert!(codec.take_replies().is_empty());
+    }
+
+    #[test]
+    fn codec_logout_server_initiated() {
+        let mut codec = TelnetCodec::with_options(4096, OptionTable::new());
+
+        codec.logout();
+        assert!(codec.is_closing());
+        assert_eq!(codec.take_replies(), vec![TelnetEvent::Will(TelnetOption::Logout)]);
+
+        // the client confirms
+        let mut bytes = BytesMut::from(vec![IAC, DO, LOGOUT]);
+        let result = consume(&mut codec, &mut bytes);
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Do(TelnetOption::Logout))),
+                Ok(Some(TelnetEvent::OptionEnabled { option: TelnetOption::Logout, side: Side::Local })),
+                Ok(Some(TelnetEvent::Logout(Side::Local))),
+            ],
+        );
+        assert_eq!(codec.take_replies(), vec![]);
+    }
+
+    #[test]
+    fn codec_logout_client() {
+        let mut codec = TelnetCodec::with_options(4096, OptionTable::new());
+
+        codec.request_logout();
+        assert_eq!(codec.take_replies(), vec![TelnetEvent::Do(TelnetOption::Logout)]);
+
+        let mut bytes = BytesMut::from(vec![IAC, WILL, LOGOUT]);
+        let result = consume(&mut codec, &mut bytes);
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Will(TelnetOption::Logout))),
+                Ok(Some(TelnetEvent::OptionEnabled { option: TelnetOption::Logout, side: Side::Remote })),
+                Ok(Some(TelnetEvent::Logout(Side::Remote))),
+            ],
+        );
+        assert!(!codec.is_closing());
+    }
+
+    #[test]
+    fn subnegotiation_terminal_type() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut bytes = BytesMut::from(vec![
+            IAC, SUBNEGOTIATION, TERMINAL_TYPE, SEND,
+            IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, TERMINAL_TYPE, IS, b'V', b'T', b'1', b'0', b'0',
+            IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, TERMINAL_TYPE, 2,
+            IAC, SUBNEGOTIATION_END,
+        ]);
+        let result = consume(&mut codec, &mut bytes);
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::TerminalType(TerminalType::Send)))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::TerminalType(
+                    TerminalType::Is(String::from("VT100")),
+                )))),
+                Err(TelnetError::InvalidSubnegotiationSequence),
+            ],
+        );
+    }
+
+    #[test]
+    fn subnegotiation_terminal_type_encode() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut output = BytesMut::new();
+
+        codec.encode(TerminalTypeCycle::request(), &mut output).expect("Invalid encoding sequence");
+        codec.encode(
+            TelnetEvent::Subnegotiation(SubnegotiationType::TerminalType(TerminalType::Is(String::from("XTERM")))),
+            &mut output,
+        ).expect("Invalid encoding sequence");
+
+        assert_eq!(
+            output,
+            BytesMut::from(vec![
+                IAC, SUBNEGOTIATION, TERMINAL_TYPE, SEND,
+                IAC, SUBNEGOTIATION_END,
+                IAC, SUBNEGOTIATION, TERMINAL_TYPE, IS, b'X', b'T', b'E', b'R', b'M',
+                IAC, SUBNEGOTIATION_END,
+            ]),
+        );
+    }
+
+    #[test]
+    fn terminal_type_cycle_repeat() {
+        let mut cycle = TerminalTypeCycle::new();
+
+        assert_eq!(cycle.receive(String::from("XTERM-256COLOR")), Some(TerminalTypeCycle::request()));
+        assert_eq!(cycle.receive(String::from("XTERM")), Some(TerminalTypeCycle::request()));
+        assert_eq!(cycle.receive(String::from("xterm")), None);
+        assert!(cycle.is_complete());
+        assert_eq!(cycle.names(), &[String::from("XTERM-256COLOR"), String::from("XTERM")][..]);
+    }
+
+    #[test]
+    fn terminal_type_cycle_wraps() {
+        let mut cycle = TerminalTypeCycle::new();
+
+        cycle.receive(String::from("ANSI"));
+        cycle.receive(String::from("VT100"));
+        assert_eq!(cycle.receive(String::from("ANSI")), None);
+        assert_eq!(cycle.into_names(), vec![String::from("ANSI"), String::from("VT100")]);
+    }
+
+    #[test]
+    fn terminal_type_cycle_limit() {
+        let mut cycle = TerminalTypeCycle::new();
+
+        for x in 0..MAX_TERMINAL_TYPES {
+            cycle.receive(format!("TYPE-{}", x));
+        }
+        assert!(cycle.is_complete());
+        assert_eq!(cycle.names().len(), MAX_TERMINAL_TYPES);
+    }
+
+    #[test]
+    fn codec_terminal_types() {
+        let mut codec = TelnetCodec::new(4096);
+
+        codec.request_terminal_types();
+        assert_eq!(codec.take_replies(), vec![TerminalTypeCycle::request()]);
+
+        for name in &["DEC-VT100", "VT100"] {
+            let mut bytes = BytesMut::from(vec![IAC, SUBNEGOTIATION, TERMINAL_TYPE, IS]);
+            bytes.extend_from_slice(name.as_bytes());
+            bytes.extend_from_slice(&[IAC, SUBNEGOTIATION_END]);
+
+            assert_eq!(consume(&mut codec, &mut bytes), vec![]);
+            assert_eq!(codec.take_replies(), vec![TerminalTypeCycle::request()]);
+        }
+
+        let mut bytes = BytesMut::from(vec![
+            IAC, SUBNEGOTIATION, TERMINAL_TYPE, IS, b'V', b'T', b'1', b'0', b'0',
+            IAC, SUBNEGOTIATION_END,
+        ]);
+        let result = consume(&mut codec, &mut bytes);
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::TerminalTypes(vec![String::from("DEC-VT100"), String::from("VT100")]))),
+            ],
+        );
+        assert_eq!(codec.take_replies(), vec![]);
+    }
+
+    #[test]
+    fn mtts_flags() {
+        let flags = MttsFlags::parse("MTTS 2829").expect("Invalid MTTS terminal type");
+
+        assert_eq!(
+            flags,
+            MttsFlags {
+                ansi: true,
+                vt100: false,
+                utf8: true,
+                colors_256: true,
+                mouse_tracking: false,
+                osc_color_palette: false,
+                screen_reader: false,
+                proxy: false,
+                truecolor: true,
+                mnes: true,
+                mslp: false,
+                ssl: true,
+            },
+        );
+        assert_eq!(flags.bits(), 2829);
+        assert_eq!(MttsFlags::from_bits(flags.bits()), flags);
+
+        assert_eq!(MttsFlags::parse("mtts 1"), Some(MttsFlags { ansi: true, ..MttsFlags::default() }));
+        assert_eq!(MttsFlags::parse("XTERM"), None);
+        assert_eq!(MttsFlags::parse("MTTS"), None);
+        assert_eq!(MttsFlags::parse("MTTS abc"), None);
+    }
+
+    #[test]
+    fn terminal_type_cycle_mtts() {
+        let mut cycle = TerminalTypeCycle::new();
+
+        for name in &["MUDLET", "ANSI-TRUECOLOR", "MTTS 2825", "MTTS 2825"] {
+            cycle.receive(String::from(*name));
+        }
+
+        assert!(cycle.is_complete());
+        let flags = cycle.mtts().expect("Missing MTTS terminal type");
+        assert!(flags.ansi && flags.colors_256 && flags.truecolor && flags.ssl);
+        assert!(!flags.utf8);
+    }
+
+    #[test]
+    fn subnegotiation_terminal_speed() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut bytes = BytesMut::from(vec![
+            IAC, SUBNEGOTIATION, TERMINAL_SPEED, SEND,
+            IAC, SUBNEGOTIATION_END,
+        ]);
+        bytes.extend_from_slice(&[IAC, SUBNEGOTIATION, TERMINAL_SPEED, IS]);
+        bytes.extend_from_slice(b"38400,9600");
+        bytes.extend_from_slice(&[IAC, SUBNEGOTIATION_END]);
+        let result = consume(&mut codec, &mut bytes);
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::TerminalSpeed(TerminalSpeed::Send)))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::TerminalSpeed(
+                    TerminalSpeed::Is { transmit: 38400, receive: 9600 },
+                )))),
+            ],
+        );
+    }
+
+    #[test]
+    fn subnegotiation_terminal_speed_invalid() {
+        for speeds in &[&b"38400"[..], b"38400,", b",9600", b"38400,9600,1200", b"fast,slow", b"-1,9600", b"99999999999,1"] {
+            let mut codec = TelnetCodec::new(4096);
+            let mut bytes = BytesMut::from(vec![IAC, SUBNEGOTIATION, TERMINAL_SPEED, IS]);
+            bytes.extend_from_slice(speeds);
+            bytes.extend_from_slice(&[IAC, SUBNEGOTIATION_END]);
+            let result = consume(&mut codec, &mut bytes);
+
+            assert_eq!(bytes.len(), 0_usize);
+            assert_eq!(result, vec![Err(TelnetError::InvalidTerminalSpeed)]);
+        }
+    }
+
+    #[test]
+    fn subnegotiation_terminal_speed_encode() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut output = BytesMut::new();
+        let message = TelnetEvent::Subnegotiation(SubnegotiationType::TerminalSpeed(
+            TerminalSpeed::Is { transmit: 115200, receive: 115200 },
+        ));
+
+        codec.encode(message, &mut output).expect("Invalid encoding sequence");
+
+        let mut expected = BytesMut::from(vec![IAC, SUBNEGOTIATION, TERMINAL_SPEED, IS]);
+        expected.extend_from_slice(b"115200,115200");
+        expected.extend_from_slice(&[IAC, SUBNEGOTIATION_END]);
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn subnegotiation_new_environ_is() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut bytes = BytesMut::from(vec![IAC, SUBNEGOTIATION, NEW_ENVIRON, IS]);
+        bytes.extend_from_slice(&[ENVIRON_VAR]);
+        bytes.extend_from_slice(b"USER");
+        bytes.extend_from_slice(&[ENVIRON_VALUE]);
+        bytes.extend_from_slice(b"joshua");
+        bytes.extend_from_slice(&[ENVIRON_VAR]);
+        bytes.extend_from_slice(b"DISPLAY");
+        bytes.extend_from_slice(&[ENVIRON_USERVAR]);
+        bytes.extend_from_slice(b"A");
+        bytes.extend_from_slice(&[ENVIRON_ESC, ENVIRON_VALUE, ENVIRON_VALUE]);
+        bytes.extend_from_slice(&[ENVIRON_USERVAR]);
+        bytes.extend_from_slice(b"EMPTY");
+        bytes.extend_from_slice(&[ENVIRON_VALUE, ENVIRON_ESC, ENVIRON_ESC, IAC, IAC]);
+        bytes.extend_from_slice(&[IAC, SUBNEGOTIATION_END]);
+        let result = consume(&mut codec, &mut bytes);
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::NewEnviron(Environ::Is(vec![
+                    EnvironVariable::var("USER", Some("joshua")),
+                    EnvironVariable::var("DISPLAY", None),
+                    EnvironVariable::user_var("A\u{1}", Some("")),
+                    EnvironVariable::user_var("EMPTY", Some("\u{2}\u{fffd}")),
+                ]))))),
+            ],
+        );
+    }
+
+    #[test]
+    fn subnegotiation_new_environ_send() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut bytes = BytesMut::from(vec![IAC, SUBNEGOTIATION, NEW_ENVIRON, SEND]);
+        bytes.extend_from_slice(&[ENVIRON_VAR]);
+        bytes.extend_from_slice(b"LANG");
+        bytes.extend_from_slice(&[ENVIRON_USERVAR]);
+        bytes.extend_from_slice(&[IAC, SUBNEGOTIATION_END]);
+        bytes.extend_from_slice(&[IAC, SUBNEGOTIATION, NEW_ENVIRON, SEND, ENVIRON_VAR, b'X', ENVIRON_VALUE, b'Y']);
+        bytes.extend_from_slice(&[IAC, SUBNEGOTIATION_END]);
+        bytes.extend_from_slice(&[IAC, SUBNEGOTIATION, NEW_ENVIRON, INFO, b'X']);
+        bytes.extend_from_slice(&[IAC, SUBNEGOTIATION_END]);
+        bytes.extend_from_slice(&[IAC, SUBNEGOTIATION, NEW_ENVIRON, INFO, ENVIRON_VAR, b'X', ENVIRON_ESC]);
+        bytes.extend_from_slice(&[IAC, SUBNEGOTIATION_END]);
+        let result = consume(&mut codec, &mut bytes);
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::NewEnviron(Environ::Send(vec![
+                    EnvironVariable::var("LANG", None),
+                    EnvironVariable::user_var("", None),
+                ]))))),
+                // SEND cannot carry values
+                Err(TelnetError::InvalidSubnegotiationSequence),
+                // data before the first variable
+                Err(TelnetError::InvalidSubnegotiationSequence),
+                // dangling escape
+                Err(TelnetError::InvalidSubnegotiationSequence),
+            ],
+        );
+    }
+
+    #[test]
+    fn subnegotiation_new_environ_encode() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut output = BytesMut::new();
+        let message = TelnetEvent::Subnegotiation(SubnegotiationType::NewEnviron(Environ::Info(vec![
+            EnvironVariable::var("USER", Some("a\u{3}b")),
+            EnvironVariable::user_var("TERM", None),
+        ])));
+
+        codec.encode(message, &mut output).expect("Invalid encoding sequence");
+
+        let mut expected = BytesMut::from(vec![IAC, SUBNEGOTIATION, NEW_ENVIRON, INFO, ENVIRON_VAR]);
+        expected.extend_from_slice(b"USER");
+        expected.extend_from_slice(&[ENVIRON_VALUE, b'a', ENVIRON_ESC, ENVIRON_USERVAR, b'b', ENVIRON_USERVAR]);
+        expected.extend_from_slice(b"TERM");
+        expected.extend_from_slice(&[IAC, SUBNEGOTIATION_END]);
+        assert_eq!(output, expected);
+
+        // round trip
+        let result = consume(&mut codec, &mut output);
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::NewEnviron(Environ::Info(vec![
+                    EnvironVariable::var("USER", Some("a\u{3}b")),
+                    EnvironVariable::user_var("TERM", None),
+                ]))))),
+            ],
+        );
+    }
+
+    #[test]
+    fn subnegotiation_environ_dialects() {
+        let mut codec = TelnetCodec::new(4096);
+        assert_eq!(codec.environ_dialect(), None);
+
+        // BSD: VAR is 1 and VALUE is 0
+        let mut bytes = BytesMut::from(vec![IAC, SUBNEGOTIATION, ENVIRON, IS, 1]);
+        bytes.extend_from_slice(b"USER");
+        bytes.extend_from_slice(&[0]);
+        bytes.extend_from_slice(b"root");
+        bytes.extend_from_slice(&[IAC, SUBNEGOTIATION_END]);
+        let result = consume(&mut codec, &mut bytes);
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Environ(
+                    Environ::Is(vec![EnvironVariable::var("USER", Some("root"))]),
+                    EnvironDialect::Bsd,
+                )))),
+            ],
+        );
+        assert_eq!(codec.environ_dialect(), Some(EnvironDialect::Bsd));
+
+        // RFC 1408
+        let mut bytes = BytesMut::from(vec![IAC, SUBNEGOTIATION, ENVIRON, INFO, 0]);
+        bytes.extend_from_slice(b"USER");
+        bytes.extend_from_slice(&[1]);
+        bytes.extend_from_slice(b"root");
+        bytes.extend_from_slice(&[IAC, SUBNEGOTIATION_END]);
+        let result = consume(&mut codec, &mut bytes);
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Environ(
+                    Environ::Info(vec![EnvironVariable::var("USER", Some("root"))]),
+                    EnvironDialect::Rfc1408,
+                )))),
+            ],
+        );
+        assert_eq!(codec.environ_dialect(), Some(EnvironDialect::Rfc1408));
+
+        // an empty reply keeps the last detected dialect
+        let mut bytes = BytesMut::from(vec![IAC, SUBNEGOTIATION, ENVIRON, IS, IAC, SUBNEGOTIATION_END]);
+        consume(&mut codec, &mut bytes);
+        assert_eq!(codec.environ_dialect(), Some(EnvironDialect::Rfc1408));
+    }
+
+    #[test]
+    fn environ_dialect_detect_uservar() {
+        assert_eq!(EnvironDialect::detect(&[IS]), EnvironDialect::Rfc1408);
+        assert_eq!(EnvironDialect::detect(&[IS, 3, b'X', 1, b'Y']), EnvironDialect::Rfc1408);
+        assert_eq!(EnvironDialect::detect(&[IS, 3, b'X', 0, b'Y']), EnvironDialect::Bsd);
+        assert_eq!(EnvironDialect::detect(&[IS, 3, b'X', 2, 0, 1, b'Y']), EnvironDialect::Rfc1408);
+        assert_eq!(EnvironDialect::detect(&[IS, 3, b'X']), EnvironDialect::Rfc1408);
+    }
+
+    #[test]
+    fn subnegotiation_environ_encode() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut output = BytesMut::new();
+        let message = TelnetEvent::Subnegotiation(SubnegotiationType::Environ(
+            Environ::Is(vec![
+                EnvironVariable::var("USER", Some("root")),
+                EnvironVariable::user_var("X", Some("1")),
+            ]),
+            EnvironDialect::Bsd,
+        ));
+
+        codec.encode(message, &mut output).expect("Invalid encoding sequence");
+
+        let mut expected = BytesMut::from(vec![IAC, SUBNEGOTIATION, ENVIRON, IS, 1]);
+        expected.extend_from_slice(b"USER");
+        expected.extend_from_slice(&[0]);
+        expected.extend_from_slice(b"root");
+        expected.extend_from_slice(&[3, b'X', 0, b'1']);
+        expected.extend_from_slice(&[IAC, SUBNEGOTIATION_END]);
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn subnegotiation_x_display_location() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut bytes = BytesMut::from(vec![
+            IAC, SUBNEGOTIATION, X_DISPLAY_LOCATION, SEND,
+            IAC, SUBNEGOTIATION_END,
+        ]);
+        for location in &[&b"sun1.example.com:0.1"[..], b":12", b"host", b"host:", b"host:0.", b"host:x"] {
+            bytes.extend_from_slice(&[IAC, SUBNEGOTIATION, X_DISPLAY_LOCATION, IS]);
+            bytes.extend_from_slice(location);
+            bytes.extend_from_slice(&[IAC, SUBNEGOTIATION_END]);
+        }
+        let result = consume(&mut codec, &mut bytes);
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::XDisplayLocation(XDisplayLocation::Send)))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::XDisplayLocation(XDisplayLocation::Is(
+                    DisplayLocation { host: String::from("sun1.example.com"), display: 0, screen: Some(1) },
+                ))))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::XDisplayLocation(XDisplayLocation::Is(
+                    DisplayLocation { host: String::new(), display: 12, screen: None },
+                ))))),
+                Err(TelnetError::InvalidDisplayLocation),
+                Err(TelnetError::InvalidDisplayLocation),
+                Err(TelnetError::InvalidDisplayLocation),
+                Err(TelnetError::InvalidDisplayLocation),
+            ],
+        );
+    }
+
+    #[test]
+    fn subnegotiation_x_display_location_encode() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut output = BytesMut::new();
+        let location = DisplayLocation::parse("localhost:10.0").expect("Invalid display location");
+        let message = TelnetEvent::Subnegotiation(SubnegotiationType::XDisplayLocation(XDisplayLocation::Is(location)));
+
+        codec.encode(message, &mut output).expect("Invalid encoding sequence");
+
+        let mut expected = BytesMut::from(vec![IAC, SUBNEGOTIATION, X_DISPLAY_LOCATION, IS]);
+        expected.extend_from_slice(b"localhost:10.0");
+        expected.extend_from_slice(&[IAC, SUBNEGOTIATION_END]);
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn message_decode_split_across_reads() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut input = BytesMut::from(vec![b'a', b'b', IAC]);
+
+        let result = consume(&mut codec, &mut input);
+        assert_eq!(result, Vec::new());
+        assert_eq!(input, BytesMut::from(vec![IAC]));
+
+        input.extend_from_slice(&[IAC, b'c', b'\n', b'd', b'\r', b'\n']);
+        let result = consume(&mut codec, &mut input);
+        assert_eq!(
+            result,
+            vec![Ok(Some(TelnetEvent::Message(String::from("ab\u{fffd}c\nd"))))],
+        );
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn subnegotiation_split_across_reads() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut input = BytesMut::from(vec![b'a', IAC, SUBNEGOTIATION, TERMINAL_TYPE, IS, b'x', IAC]);
+
+        let result = consume(&mut codec, &mut input);
+        assert_eq!(result, Vec::new());
+        assert_eq!(input, BytesMut::from(vec![IAC, SUBNEGOTIATION, TERMINAL_TYPE, IS, b'x', IAC]));
+
+        input.extend_from_slice(&[SUBNEGOTIATION_END]);
+        let result = consume(&mut codec, &mut input);
+        assert_eq!(
+            result,
+            vec![Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::TerminalType(TerminalType::Is(
+                String::from("x"),
+            )))))],
+        );
+    }
+
+    #[test]
+    fn subnegotiation_linemode_mode_decode() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut bytes = BytesMut::from(vec![
+            IAC, SUBNEGOTIATION, LINEMODE, LINEMODE_MODE, MODE_EDIT | MODE_TRAPSIG | MODE_ACK, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, LINEMODE, LINEMODE_MODE, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, LINEMODE, LINEMODE_MODE, MODE_SOFT_TAB | MODE_LIT_ECHO | MODE_ACK, IAC, SUBNEGOTIATION_END,
+        ]);
+        let result = consume(&mut codec, &mut bytes);
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Linemode(Linemode::Mode(LinemodeMode {
+                    edit: true,
+                    trapsig: true,
+                    soft_tab: false,
+                    lit_echo: false,
+                    ack: true,
+                }))))),
+                Err(TelnetError::InvalidSubnegotiationSequence),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Linemode(Linemode::Mode(LinemodeMode {
+                    edit: false,
+                    trapsig: false,
+                    soft_tab: true,
+                    lit_echo: true,
+                    ack: true,
+                }))))),
+            ],
+        );
+    }
+
+    #[test]
+    fn subnegotiation_linemode_mode_encode() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut output = BytesMut::new();
+        let mode = LinemodeMode { edit: true, trapsig: true, ..LinemodeMode::default() };
+        let message = TelnetEvent::Subnegotiation(SubnegotiationType::Linemode(Linemode::Mode(mode)));
+
+        codec.encode(message, &mut output).expect("Invalid encoding sequence");
+
+        assert_eq!(
+            output,
+            BytesMut::from(vec![IAC, SUBNEGOTIATION, LINEMODE, LINEMODE_MODE, MODE_EDIT | MODE_TRAPSIG, IAC, SUBNEGOTIATION_END]),
+        );
+    }
+
+    #[test]
+    fn codec_linemode_mode_follows_edit() {
+        let mut codec = TelnetCodec::new(4096);
+        assert!(codec.mode().edit);
+
+        // the remote end turns edit off, the codec acknowledges it and switches to characters
+        let mut input = BytesMut::from(vec![
+            b'h', b'i',
+            IAC, SUBNEGOTIATION, LINEMODE, LINEMODE_MODE, MODE_TRAPSIG, IAC, SUBNEGOTIATION_END,
+            b'x',
+        ]);
+        let result = consume(&mut codec, &mut input);
+        let mode = LinemodeMode { trapsig: true, ..LinemodeMode::default() };
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Linemode(Linemode::Mode(mode))))),
+                Ok(Some(TelnetEvent::Message(String::from("hi")))),
+                Ok(Some(TelnetEvent::Character(b'x'))),
+            ],
+        );
+        assert_eq!(codec.mode(), mode);
+        assert_eq!(
+            codec.take_replies(),
+            vec![TelnetEvent::Subnegotiation(SubnegotiationType::Linemode(Linemode::Mode(
+                LinemodeMode { ack: true, ..mode },
+            )))],
+        );
+
+        // an acknowledged mode is adopted without a reply
+        let mut input = BytesMut::from(vec![
+            IAC, SUBNEGOTIATION, LINEMODE, LINEMODE_MODE, MODE_EDIT | MODE_ACK, IAC, SUBNEGOTIATION_END,
+            b'o', b'k', b'\r', b'\n',
+        ]);
+        let result = consume(&mut codec, &mut input);
+
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[1], Ok(Some(TelnetEvent::Message(String::from("ok")))));
+        assert_eq!(codec.mode(), LinemodeMode { edit: true, ..LinemodeMode::default() });
+        assert!(codec.take_replies().is_empty());
+    }
+
+    #[test]
+    fn subnegotiation_linemode_slc_decode() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut bytes = BytesMut::from(vec![
+            IAC, SUBNEGOTIATION, LINEMODE, LINEMODE_SLC,
+            SLC_IP, SLC_VALUE | SLC_FLUSHIN | SLC_FLUSHOUT, 0x03,
+            SLC_EC, SLC_VALUE | SLC_ACK, IAC, IAC,
+            SLC_SYNCH, SLC_NOSUPPORT, 0,
+            IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, LINEMODE, LINEMODE_SLC, SLC_EC, SLC_VALUE, IAC, SUBNEGOTIATION_END,
+        ]);
+        let result = consume(&mut codec, &mut bytes);
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Linemode(Linemode::Slc(vec![
+                    SlcTriplet { function: SlcFunction::Ip, level: SlcLevel::Value, ack: false, flush_in: true, flush_out: true, value: 0x03 },
+                    SlcTriplet { function: SlcFunction::Ec, level: SlcLevel::Value, ack: true, flush_in: false, flush_out: false, value: IAC },
+                    SlcTriplet::new(SlcFunction::Synch, SlcLevel::NoSupport, 0),
+                ]))))),
+                Err(TelnetError::InvalidSubnegotiationSequence),
+            ],
+        );
+    }
+
+    #[test]
+    fn subnegotiation_linemode_slc_encode() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut output = BytesMut::new();
+        let message = TelnetEvent::Subnegotiation(SubnegotiationType::Linemode(Linemode::Slc(vec![
+            SlcTriplet::new(SlcFunction::El, SlcLevel::CantChange, 0x15),
+            SlcTriplet { function: SlcFunction::Ec, level: SlcLevel::Value, ack: true, flush_in: false, flush_out: false, value: IAC },
+        ])));
+
+        codec.encode(message, &mut output).expect("Invalid encoding sequence");
+
+        assert_eq!(
+            output,
+            BytesMut::from(vec![
+                IAC, SUBNEGOTIATION, LINEMODE, LINEMODE_SLC,
+                SLC_EL, SLC_CANTCHANGE, 0x15,
+                SLC_EC, SLC_VALUE | SLC_ACK, IAC, IAC,
+                IAC, SUBNEGOTIATION_END,
+            ]),
+        );
+    }
+
+    #[test]
+    fn slc_table_negotiation() {
+        let mut table = SlcTable::new();
+        assert_eq!(table.value(SlcFunction::Ec), Some(0x7f));
+        assert_eq!(table.value(SlcFunction::Synch), None);
+
+        let mut locked = SlcTriplet::new(SlcFunction::Ip, SlcLevel::CantChange, 0x03);
+        locked.flush_in = true;
+        table.set(locked);
+
+        let replies = table.receive(&[
+            // a new value is accepted and acknowledged
+            SlcTriplet::new(SlcFunction::Ec, SlcLevel::Value, 0x08),
+            // a value we already use needs no answer
+            SlcTriplet::new(SlcFunction::El, SlcLevel::Value, 0x15),
+            // we can't change IP, so we insist on ours
+            SlcTriplet::new(SlcFunction::Ip, SlcLevel::Value, 0x7f),
+            // the remote end wants our default
+            SlcTriplet::new(SlcFunction::Eof, SlcLevel::Default, 0),
+            // an acknowledgement is adopted silently
+            SlcTriplet { ack: true, ..SlcTriplet::new(SlcFunction::Xon, SlcLevel::Value, 0x01) },
+            // functions above 30 are not supported
+            SlcTriplet::new(SlcFunction::Other(40), SlcLevel::Value, 0x01),
+        ]);
+
+        assert_eq!(
+            replies,
+            vec![
+                SlcTriplet { ack: true, ..SlcTriplet::new(SlcFunction::Ec, SlcLevel::Value, 0x08) },
+                locked,
+                SlcTriplet::new(SlcFunction::Eof, SlcLevel::Value, 0x04),
+                SlcTriplet::new(SlcFunction::Other(40), SlcLevel::NoSupport, 0),
+            ],
+        );
+        assert_eq!(table.value(SlcFunction::Ec), Some(0x08));
+        assert_eq!(table.value(SlcFunction::Ip), Some(0x03));
+        assert_eq!(table.value(SlcFunction::Xon), Some(0x01));
+
+        // 0 SLC_VALUE 0 asks for the whole table
+        let replies = table.receive(&[SlcTriplet::new(SlcFunction::Other(0), SlcLevel::Value, 0)]);
+        assert_eq!(replies, table.triplets());
+        assert_eq!(replies.len(), 30);
+    }
+
+    #[test]
+    fn codec_slc_erase_characters() {
+        let mut codec = TelnetCodec::new(4096);
+
+        // without an SLC table DEL is ordinary data
+        let mut input = BytesMut::from(vec![b'a', 0x7f, b'\r', b'\n']);
+        let result = consume(&mut codec, &mut input);
+        assert_eq!(result, vec![Ok(Some(TelnetEvent::Message(String::from("a\u{7f}"))))]);
+
+        let mut input = BytesMut::from(vec![
+            IAC, SUBNEGOTIATION, LINEMODE, LINEMODE_SLC, SLC_EC, SLC_VALUE, 0x08, IAC, SUBNEGOTIATION_END,
+            b'a', b'b', 0x08, b'c', b'\r', b'\n',
+            b'x', 0x15, b'o', b'k', b'\r', b'\n',
+        ]);
+        let result = consume(&mut codec, &mut input);
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Linemode(Linemode::Slc(vec![
+                    SlcTriplet::new(SlcFunction::Ec, SlcLevel::Value, 0x08),
+                ]))))),
+                Ok(Some(TelnetEvent::Message(String::from("ac")))),
+                Ok(Some(TelnetEvent::Message(String::from("ok")))),
+            ],
+        );
+        assert_eq!(
+            codec.take_replies(),
+            vec![TelnetEvent::Subnegotiation(SubnegotiationType::Linemode(Linemode::Slc(vec![
+                SlcTriplet { ack: true, ..SlcTriplet::new(SlcFunction::Ec, SlcLevel::Value, 0x08) },
+            ])))],
+        );
+        assert_eq!(codec.slc().and_then(|slc| slc.value(SlcFunction::Ec)), Some(0x08));
+    }
+
+    #[test]
+    fn subnegotiation_linemode_forwardmask_decode() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut bytes = BytesMut::from(vec![
+            IAC, SUBNEGOTIATION, LINEMODE, DO, LINEMODE_FORWARDMASK, 0x00, 0x40, IAC, IAC, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, LINEMODE, DONT, LINEMODE_FORWARDMASK, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, LINEMODE, WILL, LINEMODE_FORWARDMASK, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, LINEMODE, WONT, LINEMODE_FORWARDMASK, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, LINEMODE, WILL, LINEMODE_FORWARDMASK, 0x00, IAC, SUBNEGOTIATION_END,
+        ]);
+        let result = consume(&mut codec, &mut bytes);
+
+        let mut mask = ForwardMask::new();
+        mask.insert(b'\t');
+        for character in 16..24 {
+            mask.insert(character);
+        }
+        assert!(mask.contains(b'\t'));
+        assert!(!mask.contains(b'\n'));
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Linemode(Linemode::DoForwardMask(mask))))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Linemode(Linemode::DontForwardMask)))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Linemode(Linemode::WillForwardMask)))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Linemode(Linemode::WontForwardMask)))),
+                Err(TelnetError::InvalidSubnegotiationSequence),
+            ],
+        );
+    }
+
+    #[test]
+    fn subnegotiation_linemode_forwardmask_encode() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut output = BytesMut::new();
+        let mut mask = ForwardMask::new();
+        mask.insert(b'\t');
+        mask.insert(0xf8);
+        mask.insert(0xff);
+        mask.remove(0xff);
+        let message = TelnetEvent::Subnegotiation(SubnegotiationType::Linemode(Linemode::DoForwardMask(mask)));
+
+        codec.encode(message, &mut output).expect("Invalid encoding sequence");
+
+        let mut expected = BytesMut::from(vec![IAC, SUBNEGOTIATION, LINEMODE, DO, LINEMODE_FORWARDMASK, 0x00, 0x40]);
+        expected.extend_from_slice(&[0; 29]);
+        expected.extend_from_slice(&[0x80, IAC, SUBNEGOTIATION_END]);
+        assert_eq!(output, expected);
+
+        mask.insert(0xff);
+        assert_eq!(mask.bytes()[31], 0x81);
+        output.clear();
+        codec.encode(
+            TelnetEvent::Subnegotiation(SubnegotiationType::Linemode(Linemode::DoForwardMask(ForwardMask::parse(&[IAC; 32]).unwrap()))),
+            &mut output,
+        ).expect("Invalid encoding sequence");
+        // every mask byte is doubled
+        assert_eq!(output.len(), 5 + 64 + 2);
+    }
+
+    #[test]
+    fn codec_forwardmask_partial_line() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut mask = ForwardMask::new();
+        mask.insert(b'\t');
+
+        // sending DO FORWARDMASK starts splitting lines on the masked characters
+        let mut output = BytesMut::new();
+        codec.encode(
+            TelnetEvent::Subnegotiation(SubnegotiationType::Linemode(Linemode::DoForwardMask(mask))),
+            &mut output,
+        ).expect("Invalid encoding sequence");
+        assert_eq!(codec.forward_mask(), Some(&mask));
+
+        let mut input = BytesMut::from(vec![b'l', b's', b'\t', b' ', b'-', b'l', b'\r', b'\n']);
+        let result = consume(&mut codec, &mut input);
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::PartialLine(String::from("ls\t")))),
+                Ok(Some(TelnetEvent::Message(String::from(" -l")))),
+            ],
+        );
+
+        // the client refuses the mask
+        let mut input = BytesMut::from(vec![
+            IAC, SUBNEGOTIATION, LINEMODE, WONT, LINEMODE_FORWARDMASK, IAC, SUBNEGOTIATION_END,
+            b'a', b'\t', b'\r', b'\n',
+        ]);
+        let result = consume(&mut codec, &mut input);
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Linemode(Linemode::WontForwardMask)))),
+                Ok(Some(TelnetEvent::Message(String::from("a\t")))),
+            ],
+        );
+        assert_eq!(codec.forward_mask(), None);
+    }
+
+    #[test]
+    fn subnegotiation_charset_decode() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut bytes = BytesMut::from(vec![IAC, SUBNEGOTIATION, CHARSET, CHARSET_REQUEST]);
+        bytes.extend_from_slice(b" UTF-8 ISO-8859-1");
+        bytes.extend_from_slice(&[IAC, SUBNEGOTIATION_END, IAC, SUBNEGOTIATION, CHARSET, CHARSET_REQUEST]);
+        bytes.extend_from_slice(b"[TTABLE]\x01;US-ASCII");
+        bytes.extend_from_slice(&[IAC, SUBNEGOTIATION_END, IAC, SUBNEGOTIATION, CHARSET, CHARSET_ACCEPTED]);
+        bytes.extend_from_slice(b"UTF-8");
+        bytes.extend_from_slice(&[
+            IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, CHARSET, CHARSET_REJECTED, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, CHARSET, CHARSET_TTABLE_ACK, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, CHARSET, CHARSET_TTABLE_NAK, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, CHARSET, CHARSET_TTABLE_REJECTED, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, CHARSET, CHARSET_ACCEPTED, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, CHARSET, CHARSET_REQUEST, b';', b'A', b';', b';', IAC, SUBNEGOTIATION_END,
+        ]);
+        let result = consume(&mut codec, &mut bytes);
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Charset(Charset::Request {
+                    ttable: None,
+                    charsets: vec![String::from("UTF-8"), String::from("ISO-8859-1")],
+                })))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Charset(Charset::Request {
+                    ttable: Some(1),
+                    charsets: vec![String::from("US-ASCII")],
+                })))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Charset(Charset::Accepted(String::from("UTF-8")))))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Charset(Charset::Rejected)))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Charset(Charset::TtableAck)))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Charset(Charset::TtableNak)))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Charset(Charset::TtableRejected)))),
+                Err(TelnetError::InvalidSubnegotiationSequence),
+                Err(TelnetError::InvalidSubnegotiationSequence),
+            ],
+        );
+    }
+
+    #[test]
+    fn subnegotiation_charset_ttable() {
+        let mut codec = TelnetCodec::new(4096);
+        let table = TranslationTable {
+            version: 1,
+            first: TranslationMap { charset: String::from("X-LOCAL"), size: 8, count: 2, map: vec![b'a', IAC] },
+            second: TranslationMap { charset: String::from("US-ASCII"), size: 7, count: 1, map: vec![b'z'] },
+        };
+        let mut output = BytesMut::new();
+        codec.encode(
+            TelnetEvent::Subnegotiation(SubnegotiationType::Charset(Charset::TtableIs(table))),
+            &mut output,
+        ).expect("Invalid encoding sequence");
+
+        let mut expected = BytesMut::from(vec![IAC, SUBNEGOTIATION, CHARSET, CHARSET_TTABLE_IS, 1, b';']);
+        expected.extend_from_slice(b"X-LOCAL;\x08\x00\x00\x02US-ASCII;\x07\x00\x00\x01a");
+        expected.extend_from_slice(&[IAC, IAC, b'z', IAC, SUBNEGOTIATION_END]);
+        assert_eq!(output, expected);
+
+        let result = consume(&mut codec, &mut output);
+        assert_eq!(
+            result,
+            vec![Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Charset(Charset::TtableIs(TranslationTable {
+                version: 1,
+                first: TranslationMap { charset: String::from("X-LOCAL"), size: 8, count: 2, map: vec![b'a', IAC] },
+                second: TranslationMap { charset: String::from("US-ASCII"), size: 7, count: 1, map: vec![b'z'] },
+            })))))],
+        );
+
+        // the maps must be exactly count characters long
+        let mut input = BytesMut::from(vec![IAC, SUBNEGOTIATION, CHARSET, CHARSET_TTABLE_IS, 1, b';']);
+        input.extend_from_slice(b"A;\x08\x00\x00\x02B;\x08\x00\x00\x01ab");
+        input.extend_from_slice(&[IAC, SUBNEGOTIATION_END]);
+        let result = consume(&mut codec, &mut input);
+        assert_eq!(result, vec![Err(TelnetError::InvalidSubnegotiationSequence)]);
+    }
+
+    #[test]
+    fn subnegotiation_charset_encode() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut output = BytesMut::new();
+        let message = TelnetEvent::Subnegotiation(SubnegotiationType::Charset(Charset::Request {
+            ttable: Some(1),
+            charsets: vec![String::from("UTF-8"), String::from("US-ASCII")],
+        }));
+
+        codec.encode(message, &mut output).expect("Invalid encoding sequence");
+
+        let mut expected = BytesMut::from(vec![IAC, SUBNEGOTIATION, CHARSET, CHARSET_REQUEST]);
+        expected.extend_from_slice(b"[TTABLE]\x01;UTF-8;US-ASCII");
+        expected.extend_from_slice(&[IAC, SUBNEGOTIATION_END]);
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn choose_charset_preference() {
+        let offered = vec![String::from("iso-8859-1"), String::from("utf-8"), String::from("KOI8-R")];
+
+        assert_eq!(choose_charset(&offered, &["UTF-8", "ISO-8859-1"]), Some(String::from("utf-8")));
+        assert_eq!(choose_charset(&offered, &["US-ASCII", "ISO-8859-1"]), Some(String::from("iso-8859-1")));
+        assert_eq!(choose_charset(&offered, &["US-ASCII"]), None);
+    }
+
+    #[test]
+    fn codec_charset_message_text() {
+        let mut codec = TelnetCodec::new(4096);
+        assert_eq!(codec.charset(), None);
+
+        // we accept the client's request, messages are Latin-1 from now on
+        let mut output = BytesMut::new();
+        let charset = choose_charset(&[String::from("ISO-8859-1")], &["UTF-8", "ISO-8859-1"]).unwrap();
+        codec.encode(
+            TelnetEvent::Subnegotiation(SubnegotiationType::Charset(Charset::Accepted(charset))),
+            &mut output,
+        ).expect("Invalid encoding sequence");
+        assert_eq!(codec.charset(), Some("ISO-8859-1"));
+
+        let mut input = BytesMut::from(vec![b'c', b'a', b'f', 0xe9, b'\r', b'\n']);
+        let result = consume(&mut codec, &mut input);
+        assert_eq!(result, vec![Ok(Some(TelnetEvent::Message(String::from("caf\u{e9}"))))]);
+
+        output.clear();
+        codec.encode(TelnetEvent::Message(String::from("caf\u{e9} \u{263a}")), &mut output).expect("Invalid encoding sequence");
+        assert_eq!(output, BytesMut::from(vec![b'c', b'a', b'f', 0xe9, b' ', b'?', b'\r', b'\n']));
+
+        // the client accepts our request for UTF-8
+        let mut input = BytesMut::from(vec![IAC, SUBNEGOTIATION, CHARSET, CHARSET_ACCEPTED]);
+        input.extend_from_slice(b"UTF-8");
+        input.extend_from_slice(&[IAC, SUBNEGOTIATION_END, b'c', b'a', b'f', 0xc3, 0xa9, b'\r', b'\n']);
+        let result = consume(&mut codec, &mut input);
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Charset(Charset::Accepted(String::from("UTF-8")))))),
+                Ok(Some(TelnetEvent::Message(String::from("caf\u{e9}")))),
+            ],
+        );
+        assert_eq!(codec.charset(), Some("UTF-8"));
+
+        // EBCDIC text between NVT line ends
+        codec.set_charset("IBM037");
+        let mut input = BytesMut::from(vec![0xc8, 0x89, 0x5a, b'\r', b'\n']);
+        let result = consume(&mut codec, &mut input);
+        assert_eq!(result, vec![Ok(Some(TelnetEvent::Message(String::from("Hi!"))))]);
+
+        output.clear();
+        codec.encode(TelnetEvent::Message(String::from("Hi!")), &mut output).expect("Invalid encoding sequence");
+        assert_eq!(output, BytesMut::from(vec![0xc8, 0x89, 0x5a, b'\r', b'\n']));
+    }
+
+    #[test]
+    fn subnegotiation_com_port_control_decode() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut bytes = BytesMut::from(vec![
+            IAC, SUBNEGOTIATION, COM_PORT_OPTION, COM_PORT_SIGNATURE, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, COM_PORT_OPTION, COM_PORT_SET_BAUDRATE, 0x00, 0x00, 0x96, 0x00, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, COM_PORT_OPTION, COM_PORT_SET_BAUDRATE + COM_PORT_SERVER_OFFSET, 0x00, 0x01, 0xc2, 0x00, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, COM_PORT_OPTION, COM_PORT_SET_PARITY, 1, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, COM_PORT_OPTION, COM_PORT_NOTIFY_LINESTATE + COM_PORT_SERVER_OFFSET, 0x60, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, COM_PORT_OPTION, COM_PORT_FLOWCONTROL_SUSPEND, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, COM_PORT_OPTION, COM_PORT_PURGE_DATA + COM_PORT_SERVER_OFFSET, 3, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, COM_PORT_OPTION, COM_PORT_SET_LINESTATE_MASK, IAC, IAC, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, COM_PORT_OPTION, COM_PORT_SET_DATASIZE, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, COM_PORT_OPTION, COM_PORT_PURGE_DATA + 1, IAC, SUBNEGOTIATION_END,
+        ]);
+        let result = consume(&mut codec, &mut bytes);
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::ComPortControl(ComPortControl::Client(
+                    ComPortCommand::Signature(String::new()),
+                ))))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::ComPortControl(ComPortControl::Client(
+                    ComPortCommand::SetBaudRate(38400),
+                ))))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::ComPortControl(ComPortControl::Server(
+                    ComPortCommand::SetBaudRate(115200),
+                ))))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::ComPortControl(ComPortControl::Client(
+                    ComPortCommand::SetParity(1),
+                ))))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::ComPortControl(ComPortControl::Server(
+                    ComPortCommand::NotifyLineState(0x60),
+                ))))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::ComPortControl(ComPortControl::Client(
+                    ComPortCommand::FlowControlSuspend,
+                ))))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::ComPortControl(ComPortControl::Server(
+                    ComPortCommand::PurgeData(3),
+                ))))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::ComPortControl(ComPortControl::Client(
+                    ComPortCommand::SetLineStateMask(IAC),
+                ))))),
+                Err(TelnetError::InvalidSubnegotiationSequence),
+                Err(TelnetError::InvalidSubnegotiationSequence),
+            ],
+        );
+    }
+
+    #[test]
+    fn subnegotiation_com_port_control_encode() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut output = BytesMut::new();
+
+        codec.encode(
+            TelnetEvent::Subnegotiation(SubnegotiationType::ComPortControl(ComPortControl::Client(ComPortCommand::SetBaudRate(0x0001_ff00)))),
+            &mut output,
+        ).expect("Invalid encoding sequence");
+        codec.encode(
+            TelnetEvent::Subnegotiation(SubnegotiationType::ComPortControl(ComPortControl::Server(ComPortCommand::Signature(String::from("ttyS0"))))),
+            &mut output,
+        ).expect("Invalid encoding sequence");
+        codec.encode(
+            TelnetEvent::Subnegotiation(SubnegotiationType::ComPortControl(ComPortControl::Server(ComPortCommand::FlowControlResume))),
+            &mut output,
+        ).expect("Invalid encoding sequence");
+
+        let mut expected = BytesMut::from(vec![
+            IAC, SUBNEGOTIATION, COM_PORT_OPTION, COM_PORT_SET_BAUDRATE, 0x00, 0x01, IAC, IAC, 0x00, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, COM_PORT_OPTION, COM_PORT_SIGNATURE + COM_PORT_SERVER_OFFSET,
+        ]);
+        expected.extend_from_slice(b"ttyS0");
+        expected.extend_from_slice(&[
+            IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, COM_PORT_OPTION, COM_PORT_FLOWCONTROL_RESUME + COM_PORT_SERVER_OFFSET, IAC, SUBNEGOTIATION_END,
+        ]);
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn subnegotiation_authentication_decode() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut bytes = BytesMut::from(vec![
+            IAC, SUBNEGOTIATION, AUTHENTICATION, SEND, AUTH_KERBEROS_V5, AUTH_CLIENT_TO_SERVER | AUTH_HOW_MUTUAL, AUTH_NULL, 0, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, AUTHENTICATION, IS, AUTH_KERBEROS_V5, AUTH_HOW_MUTUAL, 0, 1, IAC, IAC, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, AUTHENTICATION, AUTH_REPLY, AUTH_KERBEROS_V5, AUTH_HOW_MUTUAL, 4, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, AUTHENTICATION, AUTH_NAME, b'j', b'o', IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, AUTHENTICATION, SEND, AUTH_NULL, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, AUTHENTICATION, AUTH_REPLY, AUTH_NULL, IAC, SUBNEGOTIATION_END,
+        ]);
+        let result = consume(&mut codec, &mut bytes);
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Authentication(Authentication::Send(vec![
+                    AuthType::new(AUTH_KERBEROS_V5, AUTH_CLIENT_TO_SERVER | AUTH_HOW_MUTUAL),
+                    AuthType::new(AUTH_NULL, 0),
+                ]))))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Authentication(Authentication::Is(
+                    AuthType::new(AUTH_KERBEROS_V5, AUTH_HOW_MUTUAL),
+                    vec![0, 1, IAC],
+                ))))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Authentication(Authentication::Reply(
+                    AuthType::new(AUTH_KERBEROS_V5, AUTH_HOW_MUTUAL),
+                    vec![4],
+                ))))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Authentication(Authentication::Name(String::from("jo")))))),
+                Err(TelnetError::InvalidSubnegotiationSequence),
+                Err(TelnetError::InvalidSubnegotiationSequence),
+            ],
+        );
+    }
+
+    #[test]
+    fn subnegotiation_authentication_encode() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut output = BytesMut::new();
+
+        codec.encode(
+            TelnetEvent::Subnegotiation(SubnegotiationType::Authentication(Authentication::Send(vec![
+                AuthType::new(AUTH_SRP, AUTH_HOW_ONE_WAY),
+                AuthType::new(AUTH_NULL, 0),
+            ]))),
+            &mut output,
+        ).expect("Invalid encoding sequence");
+        codec.encode(
+            TelnetEvent::Subnegotiation(SubnegotiationType::Authentication(Authentication::Reply(AuthType::new(AUTH_SRP, 0), vec![IAC]))),
+            &mut output,
+        ).expect("Invalid encoding sequence");
+
+        assert_eq!(
+            output,
+            BytesMut::from(vec![
+                IAC, SUBNEGOTIATION, AUTHENTICATION, SEND, AUTH_SRP, AUTH_HOW_ONE_WAY, AUTH_NULL, 0, IAC, SUBNEGOTIATION_END,
+                IAC, SUBNEGOTIATION, AUTHENTICATION, AUTH_REPLY, AUTH_SRP, 0, IAC, IAC, IAC, SUBNEGOTIATION_END,
+            ]),
+        );
+    }
+
+    #[test]
+    fn codec_authentication_exchange() {
+        let mut server = TelnetCodec::new(4096);
+        let mut client = TelnetCodec::new(4096);
+        server.add_authenticator(NullAuthenticator::new());
+        client.add_authenticator(NullAuthenticator::with_name("operator"));
+
+        // the server offers NULL and the client answers with IS NULL and its name
+        let mut wire = BytesMut::new();
+        server.request_authentication();
+        server.encode_replies(&mut wire).expect("Invalid encoding sequence");
+        assert_eq!(consume(&mut client, &mut wire), Vec::new());
+        client.encode_replies(&mut wire).expect("Invalid encoding sequence");
+
+        let mut expected = BytesMut::from(vec![IAC, SUBNEGOTIATION, AUTHENTICATION, IS, AUTH_NULL, 0]);
+        expected.extend_from_slice(b"operator");
+        expected.extend_from_slice(&[IAC, SUBNEGOTIATION_END]);
+        assert_eq!(wire, expected);
+
+        let result = consume(&mut server, &mut wire);
+        assert_eq!(
+            result,
+            vec![Ok(Some(TelnetEvent::Authentication {
+                auth_type: AuthType::new(AUTH_NULL, 0),
+                result: AuthResult::Accepted(Some(String::from("operator"))),
+            }))],
+        );
+        assert!(server.take_replies().is_empty());
+
+        // a client without a matching mechanism answers IS NULL, which this server rejects
+        let mut server = TelnetCodec::new(4096);
+        server.add_authenticator(NullAuthenticator::new());
+        let mut client = TelnetCodec::new(4096);
+        client.add_authenticator(NullAuthenticator::with_name("operator"));
+        let mut input = BytesMut::from(vec![
+            IAC, SUBNEGOTIATION, AUTHENTICATION, SEND, AUTH_KERBEROS_V5, 0, IAC, SUBNEGOTIATION_END,
+        ]);
+        assert_eq!(consume(&mut client, &mut input), Vec::new());
+        assert_eq!(
+            client.take_replies(),
+            vec![TelnetEvent::Subnegotiation(SubnegotiationType::Authentication(Authentication::Is(AuthType::new(AUTH_NULL, 0), Vec::new())))],
+        );
+
+        let mut input = BytesMut::from(vec![
+            IAC, SUBNEGOTIATION, AUTHENTICATION, IS, AUTH_KERBEROS_V5, 0, 1, 2, IAC, SUBNEGOTIATION_END,
+        ]);
+        let result = consume(&mut server, &mut input);
+        assert_eq!(
+            result,
+            vec![Ok(Some(TelnetEvent::Authentication { auth_type: AuthType::new(AUTH_KERBEROS_V5, 0), result: AuthResult::Rejected }))],
+        );
+
+        // the client is told its mechanism was rejected instead of waiting for a REPLY
+        let mut output = BytesMut::new();
+        server.encode_replies(&mut output).expect("Invalid encoding sequence");
+        assert_eq!(
+            output,
+            BytesMut::from(vec![
+                IAC, SUBNEGOTIATION, AUTHENTICATION, AUTH_REPLY, AUTH_KERBEROS_V5, 0, AUTH_REPLY_REJECT, IAC, SUBNEGOTIATION_END,
+            ]),
+        );
+    }
+
+    #[test]
+    fn subnegotiation_encrypt_decode() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut bytes = BytesMut::from(vec![
+            IAC, SUBNEGOTIATION, ENCRYPT, ENCRYPT_SUPPORT, ENCTYPE_DES_CFB64, ENCTYPE_DES_OFB64, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, ENCRYPT, IS, ENCTYPE_DES_CFB64, 1, IAC, IAC, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, ENCRYPT, ENCRYPT_REPLY, ENCTYPE_DES_CFB64, 2, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, ENCRYPT, ENCRYPT_REQUEST_START, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, ENCRYPT, ENCRYPT_REQUEST_END, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, ENCRYPT, ENCRYPT_ENC_KEYID, 0, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, ENCRYPT, ENCRYPT_DEC_KEYID, 1, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, ENCRYPT, ENCRYPT_END, 0, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, ENCRYPT, ENCRYPT_SUPPORT, IAC, SUBNEGOTIATION_END,
+        ]);
+        let result = consume(&mut codec, &mut bytes);
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Encrypt(Encrypt::Support(vec![ENCTYPE_DES_CFB64, ENCTYPE_DES_OFB64]))))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Encrypt(Encrypt::Is(ENCTYPE_DES_CFB64, vec![1, IAC]))))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Encrypt(Encrypt::Reply(ENCTYPE_DES_CFB64, vec![2]))))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Encrypt(Encrypt::RequestStart(Vec::new()))))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Encrypt(Encrypt::RequestEnd)))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Encrypt(Encrypt::EncKeyId(vec![0]))))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Encrypt(Encrypt::DecKeyId(vec![1]))))),
+                Err(TelnetError::InvalidSubnegotiationSequence),
+                Err(TelnetError::InvalidSubnegotiationSequence),
+            ],
+        );
+    }
+
+    #[test]
+    fn codec_encrypt_output() {
+        let mut codec = TelnetCodec::new(4096);
+        codec.set_encrypt_cipher(XorCipher(0x20));
+        let mut output = BytesMut::new();
+
+        codec.encode(TelnetEvent::Subnegotiation(SubnegotiationType::Encrypt(Encrypt::Start(vec![0]))), &mut output).expect("Invalid encoding sequence");
+        assert!(codec.is_encrypting());
+        codec.encode(TelnetEvent::Message(String::from("hi")), &mut output).expect("Invalid encoding sequence");
+        codec.encode(TelnetEvent::Subnegotiation(SubnegotiationType::Encrypt(Encrypt::End)), &mut output).expect("Invalid encoding sequence");
+        assert!(!codec.is_encrypting());
+        codec.encode(TelnetEvent::Message(String::from("ok")), &mut output).expect("Invalid encoding sequence");
+
+        let mut expected = BytesMut::from(vec![IAC, SUBNEGOTIATION, ENCRYPT, ENCRYPT_START, 0, IAC, SUBNEGOTIATION_END]);
+        expected.extend_from_slice(&[b'h' ^ 0x20, b'i' ^ 0x20, b'\r' ^ 0x20, b'\n' ^ 0x20]);
+        expected.extend_from_slice(&[IAC ^ 0x20, SUBNEGOTIATION ^ 0x20, ENCRYPT ^ 0x20, ENCRYPT_END ^ 0x20, IAC ^ 0x20, SUBNEGOTIATION_END ^ 0x20]);
+        expected.extend_from_slice(b"ok\r\n");
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn codec_decrypt_input() {
+        let mut sender = TelnetCodec::new(4096);
+        sender.set_encrypt_cipher(XorCipher(0x5a));
+        let mut wire = BytesMut::new();
+        for event in [
+            TelnetEvent::Message(String::from("plain")),
+            TelnetEvent::Subnegotiation(SubnegotiationType::Encrypt(Encrypt::Start(vec![0]))),
+            TelnetEvent::Message(String::from("secret \u{ff}")),
+            TelnetEvent::Subnegotiation(SubnegotiationType::NegotiateAboutWindowSize(80, 24)),
+            TelnetEvent::Subnegotiation(SubnegotiationType::Encrypt(Encrypt::End)),
+            TelnetEvent::Message(String::from("clear")),
+        ] {
+            sender.encode(event, &mut wire).expect("Invalid encoding sequence");
+        }
+
+        let mut receiver = TelnetCodec::new(4096);
+        receiver.set_decrypt_cipher(XorCipher(0x5a));
+
+        // feed the stream a few bytes at a time so the END sequence is split across reads
+        let mut input = BytesMut::new();
+        let mut result = Vec::new();
+        while !wire.is_empty() {
+            let chunk = wire.split_to(std::cmp::min(3, wire.len()));
+            input.extend_from_slice(&chunk);
+            result.extend(consume(&mut receiver, &mut input));
+        }
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Message(String::from("plain")))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Encrypt(Encrypt::Start(vec![0]))))),
+                Ok(Some(TelnetEvent::Message(String::from("secret \u{ff}")))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::NegotiateAboutWindowSize(80, 24)))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Encrypt(Encrypt::End)))),
+                Ok(Some(TelnetEvent::Message(String::from("clear")))),
+            ],
+        );
+        assert!(!receiver.is_decrypting());
+    }
+
+    #[test]
+    fn codec_start_tls_server() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut output = BytesMut::new();
+        codec.start_tls();
+        codec.encode_replies(&mut output).expect("Invalid encoding sequence");
+        assert_eq!(output, BytesMut::from(vec![IAC, SUBNEGOTIATION, START_TLS, START_TLS_FOLLOWS, IAC, SUBNEGOTIATION_END]));
+
+        // the client's FOLLOWS is followed straight away by its TLS ClientHello
+        let mut input = BytesMut::from(vec![
+            b'h', b'i', b'\r', b'\n',
+            IAC, SUBNEGOTIATION, START_TLS, START_TLS_FOLLOWS, IAC, SUBNEGOTIATION_END,
+            0x16, 0x03, 0x01, IAC, b'\r', b'\n',
+        ]);
+        let result = consume(&mut codec, &mut input);
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Message(String::from("hi")))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::StartTlsFollows))),
+            ],
+        );
+        assert!(codec.is_tls_ready());
+        assert!(codec.take_replies().is_empty());
+        assert_eq!(input, BytesMut::from(vec![0x16, 0x03, 0x01, IAC, b'\r', b'\n']));
+        assert_eq!(codec.decode(&mut input), Ok(None));
+        assert_eq!(input.len(), 6);
+    }
+
+    #[test]
+    fn codec_start_tls_client() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut input = BytesMut::from(vec![
+            IAC, SUBNEGOTIATION, START_TLS, START_TLS_FOLLOWS, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, START_TLS, 2, IAC, SUBNEGOTIATION_END,
+        ]);
+        let result = consume(&mut codec, &mut input);
+
+        // the client answers with its own FOLLOWS before starting the handshake
+        assert_eq!(result, vec![Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::StartTlsFollows)))]);
+        assert!(codec.is_tls_ready());
+        assert_eq!(codec.take_replies(), vec![TelnetEvent::Subnegotiation(SubnegotiationType::StartTlsFollows)]);
+        assert_eq!(input.len(), 6);
+    }
+
+    #[test]
+    fn subnegotiation_send_location() {
+        let mut codec = TelnetCodec::new(4096);
+        assert_eq!(codec.location(), None);
+
+        let mut bytes = BytesMut::from(vec![IAC, SUBNEGOTIATION, SEND_LOCATION]);
+        bytes.extend_from_slice(b"Building 4, Room 101");
+        bytes.extend_from_slice(&[IAC, SUBNEGOTIATION_END]);
+        let result = consume(&mut codec, &mut bytes);
+
+        assert_eq!(
+            result,
+            vec![Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::SendLocation(String::from("Building 4, Room 101")))))],
+        );
+        assert_eq!(codec.location(), Some("Building 4, Room 101"));
+
+        let mut output = BytesMut::new();
+        codec.encode(
+            TelnetEvent::Subnegotiation(SubnegotiationType::SendLocation(String::from("Lab"))),
+            &mut output,
+        ).expect("Invalid encoding sequence");
+        assert_eq!(output, BytesMut::from(vec![IAC, SUBNEGOTIATION, SEND_LOCATION, b'L', b'a', b'b', IAC, SUBNEGOTIATION_END]));
+    }
+
+    #[test]
+    fn subnegotiation_tn3270e_device_type() {
+        let mut codec = TelnetCodec::new(4096);
+        let mut bytes = BytesMut::from(vec![IAC, SUBNEGOTIATION, TN3270E, TN3270E_SEND, TN3270E_DEVICE_TYPE, IAC, SUBNEGOTIATION_END]);
+        bytes.extend_from_slice(&[IAC, SUBNEGOTIATION, TN3270E, TN3270E_DEVICE_TYPE, TN3270E_REQUEST]);
+        bytes.extend_from_slice(b"IBM-3278-2-E");
+        bytes.extend_from_slice(&[TN3270E_CONNECT]);
+        bytes.extend_from_slice(b"LU01");
+        bytes.extend_from_slice(&[IAC, SUBNEGOTIATION_END, IAC, SUBNEGOTIATION, TN3270E, TN3270E_DEVICE_TYPE, TN3270E_IS]);
+        bytes.extend_from_slice(b"IBM-3278-2-E");
+        bytes.extend_from_slice(&[TN3270E_CONNECT]);
+        bytes.extend_from_slice(b"LU01");
+        bytes.extend_from_slice(&[
+            IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, TN3270E, TN3270E_DEVICE_TYPE, TN3270E_REJECT, TN3270E_REASON, TN3270E_REASON_DEVICE_IN_USE, IAC, SUBNEGOTIATION_END,
+            IAC, SUBNEGOTIATION, TN3270E, TN3270E_DEVICE_TYPE, TN3270E_IS, IAC, SUBNEGOTIATION_END,
+        ]);
+        let result = consume(&mut codec, &mut bytes);
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Tn3270e(Tn3270e::SendDeviceType)))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Tn3270e(Tn3270e::DeviceTypeRequest {
+                    device_type: String::from("IBM-3278-2-E"),
+                    resource: Some(DeviceResource::Connect(String::from("LU01"))),
+                })))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Tn3270e(Tn3270e::DeviceTypeIs {
+                    device_type: String::from("IBM-3278-2-E"),
+                    device_name: String::from("LU01"),
+                })))),
+                Ok(Some(TelnetEvent::Subnegotiation(SubnegotiationType::Tn3270e(Tn3270e::DeviceTypeReject(TN3270E_REASON_DEVICE_IN_USE))))),
+                Err(TelnetError::InvalidSubnegotiationSequence),
+            ],
+        );
+
+        let mut output = BytesMut::new();
+        codec.encode(
+            TelnetEvent::Subnegotiation(SubnegotiationType::Tn3270e(Tn3270e::DeviceTypeRequest {
+                device_type: String::from("IBM-3287-1"),
+                resource: Some(DeviceResource::Associate(String::from("LU01"))),
+            })),
+            &mut output,
+        ).expect("Invalid encoding sequence");
+        let mut expected = BytesMut::from(vec![IAC, SUBNEGOTIATION, TN3270E, TN3270E_DEVICE_TYPE, TN3270E_REQUEST]);
+        expected.extend_from_slice(b"IBM-3287-1");
+        expected.extend_from_slice(&[TN3270E_ASSOCIATE]);
+        expected.extend_from_slice(b"LU01");
+        expected.extend_from_slice(&[IAC, SUBNEGOTIATION_END]);
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn codec_tn3270e_functions() {
+        let mut codec = TelnetCodec::new(4096);
+        codec.set_tn3270e_functions(vec![TN3270E_BIND_IMAGE, TN3270E_RESPONSES]);
+        let mut bytes = BytesMut::from(vec![
+            IAC, SUBNEGOTIATION, TN3270E, TN3270E_FUNCTIONS, TN3270E_REQUEST, TN3270E_RESPONSES, TN3270E_SYSREQ, IAC, SUBNEGOTIATION_END,
+        ]);
+        consume(&mut codec, &mut bytes);
+
+        // SYSREQ isn't supported, so counter with the rest
+        assert_eq!(
+            codec.take_replies(),
+            vec![TelnetEvent::Subnegotiation(SubnegotiationType::Tn3270e(Tn3270e::FunctionsRequest(vec![TN3270E_RESPONSES])))],
+        );
+        assert_eq!(codec.tn3270e_functions(), None);
+
+        let mut bytes = BytesMut::from(vec![
+            IAC, SUBNEGOTIATION, TN3270E, TN3270E_FUNCTIONS, TN3270E_REQUEST, TN3270E_RESPONSES, IAC, SUBNEGOTIATION_END,
+        ]);
+        consume(&mut codec, &mut bytes);
+        let mut output = BytesMut::new();
+        codec.encode_replies(&mut output).expect("Invalid encoding sequence");
+
+        assert_eq!(
+            output,
+            BytesMut::from(vec![IAC, SUBNEGOTIATION, TN3270E, TN3270E_FUNCTIONS, TN3270E_IS, TN3270E_RESPONSES, IAC, SUBNEGOTIATION_END]),
+        );
+        assert_eq!(codec.tn3270e_functions(), Some(&[TN3270E_RESPONSES][..]));
+    }
+
+    #[test]
+    fn codec_tn3270e_records() {
+        let policy = OptionPolicy::new().local(TelnetOption::Tn3270e);
+        let mut codec = TelnetCodec::with_options(4096, OptionTable::with_policy(policy));
+        let mut bytes = BytesMut::from(vec![
+            IAC, DO, TN3270E,
+            TN3270E_DT_3270_DATA, 0, TN3270E_RSF_ALWAYS_RESPONSE, 0x01, 0x02, 0xf5, b'\r', b'\n', IAC, IAC,
+        ]);
+        let mut result = consume(&mut codec, &mut bytes);
+        assert!(codec.is_tn3270e());
+
+        // the rest of the record arrives later
+        bytes.extend_from_slice(&[0x11, IAC, END_OF_RECORD_MARK, 0, 0, IAC, END_OF_RECORD_MARK]);
+        result.extend(consume(&mut codec, &mut bytes));
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(Some(TelnetEvent::Do(TelnetOption::Tn3270e))),
+                Ok(Some(TelnetEvent::OptionEnabled { option: TelnetOption::Tn3270e, side: Side::Local })),
+                Ok(Some(TelnetEvent::Tn3270eRecord(Tn3270eRecord {
+                    data_type: Tn3270eDataType::Data3270,
+                    request_flag: 0,
+                    response_flag: TN3270E_RSF_ALWAYS_RESPONSE,
+                    seq_number: 0x0102,
+                    payload: vec![0xf5, b'\r', b'\n', IAC, 0x11],
+                }))),
+                Err(TelnetError::InvalidRecordHeader),
+            ],
+        );
+
+        let mut output = BytesMut::new();
+        let mut record = Tn3270eRecord::new(Tn3270eDataType::Response, vec![IAC]);
+        record.seq_number = 0x0102;
+        codec.encode(TelnetEvent::Tn3270eRecord(record), &mut output).expect("Invalid encoding sequence");
+        assert_eq!(
+            output,
+            BytesMut::from(vec![TN3270E_DT_RESPONSE, 0, 0, 0x01, 0x02, IAC, IAC, IAC, END_OF_RECORD_MARK]),
+        );
+
+        let mut bytes = BytesMut::from(vec![IAC, DONT, TN3270E]);
+        consume(&mut codec, &mut bytes);
+        assert!(!codec.is_tn3270e());
+    }
+
+    fn login_screen() -> Command {
+        // a 2x20 screen with a protected prompt, an 8 character input field and a status line
+        let ebcdic = TextEncoding::Ebcdic037;
+        Command::EraseWrite(
+            Wcc::from_bits(WCC_RESET | WCC_KEYBOARD_RESTORE | WCC_RESET_MDT),
+            vec![
+                Order::SetBufferAddress(0),
+                Order::StartField(0x60),
+                Order::Data(ebcdic.encode("USER:")),
+                Order::StartField(0x40),
+                Order::InsertCursor,
+                Order::SetBufferAddress(15),
+                Order::StartField(0x60),
+                Order::SetBufferAddress(20),
+                Order::StartField(0xe8),
+                Order::Data(ebcdic.encode("READY")),
+                Order::StartField(0x60),
+            ],
+        )
+    }
+
+    #[test]
+    fn codec_tn3270e_record_longer_than_buffer() {
+        let mut codec = TelnetCodec::new(4096);
+        codec.set_tn3270e(true);
+        let payload: Vec<u8> = (0..4371).map(|index| (index % 255) as u8).collect();
+        let record = Tn3270eRecord::new(Tn3270eDataType::Data3270, payload.clone());
+        let mut bytes = BytesMut::new();
+        codec.encode(TelnetEvent::Tn3270eRecord(record), &mut bytes).expect("Invalid encoding sequence");
+        let result = consume(&mut codec, &mut bytes);
+
+        // the line length cap doesn't cut records short
+        assert_eq!(result, vec![Ok(Some(TelnetEvent::Tn3270eRecord(Tn3270eRecord::new(Tn3270eDataType::Data3270, payload))))]);
+    }
+
+    #[test]
+    fn charset_ebcdic() {
+        let ebcdic = TextEncoding::from_name("ibm037").expect("IBM037 is supported");
+        assert_eq!(ebcdic.encode("Hi 1!"), vec![0xc8, 0x89, 0x40, 0xf1, 0x5a]);
+        assert_eq!(ebcdic.encode("\u{20ac}"), vec![0x6f]);
+        assert_eq!(ebcdic.decode(&[0xc8, 0x89, 0x40, 0xf1, 0x5a]), String::from("Hi 1!"));
+    }
+
+    #[test]
+    fn data_stream_parse() {
+        let bytes = vec![
+            CMD_ERASE_WRITE, 0x43,
+            ORDER_SBA, 0x40, 0x40, ORDER_SF, 0x60, 0xe4, 0xe2, 0xc5, 0xd9, 0x7a,
+            ORDER_SF, 0x40, ORDER_IC,
+            ORDER_SBA, 0x40, 0x4f, ORDER_SF, 0x60,
+            ORDER_SBA, 0x40, 0xd4, ORDER_SF, 0xe8, 0xd9, 0xc5, 0xc1, 0xc4, 0xe8, ORDER_SF, 0x60,
+        ];
+        let command = Command::parse(&bytes).expect("Invalid data stream");
+        assert_eq!(command, login_screen());
+        assert_eq!(command.bytes(), bytes);
+
+        // SNA command codes and 14 bit addresses
+        let bytes = vec![
+            CMD_SNA_WRITE, 0,
+            ORDER_SBA, 0x10, 0x00, ORDER_SFE, 2, XA_3270_FIELD_ATTRIBUTE, 0x20, 0x42, 0xf2,
+            ORDER_RA, 0x10, 0x50, 0x40, ORDER_EUA, 0x5d, 0x7f, ORDER_PT, ORDER_GE, 0xad,
+            ORDER_RA, 0x10, 0x60, ORDER_GE, 0xad,
+        ];
+        assert_eq!(
+            Command::parse(&bytes),
+            Ok(Command::Write(Wcc::default(), vec![
+                Order::SetBufferAddress(0x1000),
+                Order::StartFieldExtended(vec![(XA_3270_FIELD_ATTRIBUTE, 0x20), (0x42, 0xf2)]),
+                Order::RepeatToAddress(0x1050, 0x40, false),
+                Order::EraseUnprotectedToAddress(0x77f),
+                Order::ProgramTab,
+                Order::GraphicEscape(0xad),
+                Order::RepeatToAddress(0x1060, 0xad, true),
+            ])),
+        );
+        let write = Command::Write(Wcc::default(), vec![
+            Order::RepeatToAddress(20, 0xad, true),
+            Order::RepeatToAddress(40, 0x5c, false),
+        ]);
+        let bytes = write.bytes();
+        assert_eq!(bytes, vec![CMD_WRITE, 0, ORDER_RA, 0x40, 0xd4, ORDER_GE, 0xad, ORDER_RA, 0x40, 0xe8, 0x5c]);
+        assert_eq!(Command::parse(&bytes), Ok(write));
+        assert_eq!(Command::parse(&[CMD_WRITE, 0, ORDER_RA, 0x40, 0xd4, ORDER_GE]), Err(TelnetError::InvalidDataStream));
+        assert_eq!(encode_address(0x77f), [0x5d, 0x7f]);
+        assert_eq!(decode_address(0x5d, 0x7f), 0x77f);
+
+        let fields = [0x00, 0x06, 0x01, 0xff, 0x02, 0xf3, 0x00, 0x00, 0x81, 0x01];
+        let command = [&[CMD_WRITE_STRUCTURED_FIELD][..], &fields[..]].concat();
+        assert_eq!(
+            Command::parse(&command),
+            Ok(Command::WriteStructuredField(vec![
+                StructuredField { id: 0x01, data: vec![0xff, 0x02, 0xf3] },
+                StructuredField { id: 0x81, data: vec![0x01] },
+            ])),
+        );
+
+        assert_eq!(Command::parse(&[CMD_WRITE, 0, ORDER_SBA, 0x40]), Err(TelnetError::InvalidDataStream));
+        assert_eq!(Command::parse(&[0x42]), Err(TelnetError::InvalidDataStream));
+        assert_eq!(Command::parse(&[CMD_SNA_READ_MODIFIED]), Ok(Command::ReadModified));
+    }
+
+    #[test]
+    fn data_stream_screen() {
+        let mut screen = Screen::new(2, 20).expect("Valid screen size");
+        assert_eq!(screen.apply(&login_screen()), None);
+
+        assert_eq!(screen.text(), String::from(" USER:              \n READY              "));
+        assert_eq!(screen.cursor(), 7);
+        assert!(!screen.is_keyboard_locked());
+        let fields = screen.fields();
+        assert_eq!(
+            fields,
+            vec![
+                Field { address: 0, start: 1, length: 5, attribute: 0x60 },
+                Field { address: 6, start: 7, length: 8, attribute: 0x40 },
+                Field { address: 15, start: 16, length: 4, attribute: 0x60 },
+                Field { address: 20, start: 21, length: 5, attribute: 0xe8 },
+                Field { address: 26, start: 27, length: 13, attribute: 0x60 },
+            ],
+        );
+        assert!(fields[3].is_protected() && fields[3].is_intensified() && !fields[3].is_hidden());
+        assert_eq!(screen.field_text(&fields[3]), String::from("READY"));
+        assert_eq!(screen.field_at(3), Some(fields[0]));
+        assert_eq!(screen.field_at(26), Some(fields[4]));
+        assert_eq!(screen.field_at(39), Some(fields[4]));
+
+        screen.type_text("bob").expect("Unprotected field");
+        assert_eq!(screen.field_at(9).map(|field| field.is_modified()), Some(true));
+        let mut expected = vec![AID_ENTER, 0x40, 0x4a, ORDER_SBA, 0x40, 0xc7];
+        expected.extend(TextEncoding::Ebcdic037.encode("bob"));
+        assert_eq!(screen.press(Aid::Enter), expected);
+        assert!(screen.is_keyboard_locked());
+
+        // a full field skips to the next unprotected one, which wraps back to the same field
+        screen.set_cursor(12);
+        screen.type_text("abcd").expect("Unprotected field");
+        assert_eq!(screen.cursor(), 8);
+        assert_eq!(screen.field_text(&screen.fields()[1]), String::from("dobabc"));
+
+        screen.set_cursor(2);
+        assert_eq!(screen.type_text("x"), Err(TelnetError::ProtectedField));
+        screen.set_cursor(6);
+        assert_eq!(screen.type_text("x"), Err(TelnetError::ProtectedField));
+
+        // the host repeats a character over the input field and erases it again
+        let write = Command::Write(
+            Wcc::from_bits(WCC_KEYBOARD_RESTORE | WCC_SOUND_ALARM),
+            vec![Order::SetBufferAddress(7), Order::RepeatToAddress(15, 0x5c, false), Order::SetBufferAddress(0)],
+        );
+        assert_eq!(screen.apply(&write), None);
+        assert!(screen.alarm());
+        assert!(!screen.is_keyboard_locked());
+        assert_eq!(screen.row_text(0), String::from(" USER: ********     "));
+        screen.apply(&Command::Write(Wcc::default(), vec![Order::EraseUnprotectedToAddress(20)]));
+        assert_eq!(screen.row_text(0), String::from(" USER:              "));
+        assert_eq!(screen.row_text(1), String::from(" READY              "));
+        assert_eq!(screen.row_text(screen.rows()), screen.row_text(0));
+    }
+
+    #[test]
+    fn data_stream_screen_size() {
+        assert_eq!(Screen::new(0, 80).err(), Some(TelnetError::InvalidScreenSize));
+        assert_eq!(Screen::new(24, 0).err(), Some(TelnetError::InvalidScreenSize));
+        assert_eq!(Screen::new(200, 400).err(), Some(TelnetError::InvalidScreenSize));
+        assert_eq!(Screen::new(1, 0x4001).err(), Some(TelnetError::InvalidScreenSize));
+
+        let mut screen = Screen::new(27, 132).expect("Valid screen size");
+        screen.set_cursor(27 * 132);
+        assert_eq!(screen.cursor(), 0);
+
+        // the last position of the largest screen takes a 14 bit address
+        let mut screen = Screen::new(128, 128).expect("Valid screen size");
+        screen.apply(&Command::Write(Wcc::default(), vec![Order::SetBufferAddress(0x3fff), Order::Data(vec![0xc1])]));
+        assert!(screen.row_text(127).ends_with('A'));
+    }
+
+    #[test]
+    fn data_stream_read_commands() {
+        let mut screen = Screen::new(2, 20).expect("Valid screen size");
+        screen.apply(&login_screen());
+        screen.type_text("ab").expect("Unprotected field");
+
+        let mut expected = vec![AID_NO_AID, 0x40, 0xc9, ORDER_SBA, 0x40, 0xc7];
+        expected.extend(TextEncoding::Ebcdic037.encode("ab"));
+        assert_eq!(screen.apply(&Command::ReadModified), Some(expected));
+
+        let buffer = screen.apply(&Command::ReadBuffer).expect("Read Buffer is answered");
+        assert_eq!(buffer.len(), 3 + 40 + 5);
+        assert_eq!(&buffer[..6], &[AID_NO_AID, 0x40, 0xc9, ORDER_SF, 0x60, 0xe4]);
+
+        screen.apply(&Command::EraseAllUnprotected);
+        assert_eq!(screen.cursor(), 7);
+        assert_eq!(screen.apply(&Command::ReadModified), Some(vec![AID_NO_AID, 0x40, 0xc7]));
+
+        // short reads only send the AID unless the host asks for everything
+        assert_eq!(screen.press(Aid::Pa(1)), vec![AID_PA1]);
+        assert_eq!(screen.apply(&Command::ReadModified), Some(vec![AID_PA1]));
+        assert_eq!(screen.apply(&Command::ReadModifiedAll), Some(vec![AID_PA1, 0x40, 0xc7]));
+        assert_eq!(screen.press(Aid::Clear), vec![AID_CLEAR]);
+        assert!(screen.fields().is_empty());
+        assert_eq!(screen.cursor(), 0);
+
+        // an unformatted screen sends all of its text
+        screen.type_text("hi").expect("Unformatted screen");
+        let mut expected = vec![0xf3, 0x40, 0xc2];
+        expected.extend(TextEncoding::Ebcdic037.encode("hi"));
+        assert_eq!(screen.press(Aid::Pf(3)), expected);
+        screen.set_cursor(5);
+        screen.apply(&Command::EraseAllUnprotected);
+        assert_eq!(screen.cursor(), 0);
+        assert_eq!(screen.row_text(0), " ".repeat(20));
+        assert_eq!(screen.apply(&Command::ReadModified), Some(vec![AID_NO_AID, 0x40, 0x40]));
+
+        assert_eq!(u8::from(Aid::Pf(24)), 0x4c);
+        assert_eq!(Aid::from(0xc1), Aid::Pf(13));
+        assert_eq!(Aid::from(AID_SYSREQ), Aid::SysReq);
+    }
+
+    #[test]
+    fn codec_tn3270e_screen() {
+        let mut codec = TelnetCodec::new(4096);
+        codec.set_tn3270e(true);
+        let record = Tn3270eRecord::new(Tn3270eDataType::Data3270, login_screen().bytes());
+        let mut bytes = BytesMut::new();
+        codec.encode(TelnetEvent::Tn3270eRecord(record), &mut bytes).expect("Invalid encoding sequence");
+
+        let mut screen = Screen::new(2, 20).expect("Valid screen size");
+        for event in consume(&mut codec, &mut bytes) {
+            if let Ok(Some(TelnetEvent::Tn3270eRecord(record))) = event {
+                assert_eq!(record.data_type, Tn3270eDataType::Data3270);
+                let command = Command::parse(&record.payload).expect("Invalid data stream");
+                screen.apply(&command);
+            }
+        }
+
+        assert_eq!(screen.row_text(1), String::from(" READY              "));
+        screen.type_text("me").expect("Unprotected field");
+        let reply = Tn3270eRecord::new(Tn3270eDataType::Data3270, screen.press(Aid::Enter));
+        let mut output = BytesMut::new();
+        codec.encode(TelnetEvent::Tn3270eRecord(reply), &mut output).expect("Invalid encoding sequence");
+        assert_eq!(
+            output,
+            BytesMut::from(vec![0, 0, 0, 0, 0, AID_ENTER, 0x40, 0xc9, ORDER_SBA, 0x40, 0xc7, 0x94, 0x85, IAC, END_OF_RECORD_MARK]),
+        );
+    }
 }
@@ -0,0 +1,521 @@
+use crate::option::*;
+use crate::event::*;
+use crate::clock::*;
+use std::collections::VecDeque;
+use std::time::{ Duration, Instant };
+
+// RFC 1143 "Q method" option states
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum QState {
+  No,
+  Yes,
+  WantNo,
+  WantYes,
+}
+
+// the queue bit, set when the opposite request arrives while one is in flight
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum QueueState {
+  Empty,
+  Opposite,
+}
+
+// which end of the connection performs an option
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Side {
+  Local,
+  Remote,
+}
+
+// us: whether we perform the option, him: whether the remote performs it
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct OptionState {
+  pub us: QState,
+  pub usq: QueueState,
+  pub him: QState,
+  pub himq: QueueState,
+}
+
+impl Default for OptionState {
+  fn default() -> OptionState {
+    OptionState {
+      us: QState::No,
+      usq: QueueState::Empty,
+      him: QState::No,
+      himq: QueueState::Empty,
+    }
+  }
+}
+
+fn pending(state: QState) -> bool {
+  state == QState::WantYes || state == QState::WantNo
+}
+
+// identifies a DO TIMING-MARK we sent, handed back when the remote end answers it
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub struct TimingMarkToken(u64);
+
+// a request we sent that has not been answered yet
+struct Request {
+  option: TelnetOption,
+  side: Side,
+  deadline: Instant,
+}
+
+// an option stays in effect until the remote end confirms it is disabled
+fn in_effect(state: QState) -> bool {
+  state == QState::Yes || state == QState::WantNo
+}
+
+// what should be sent to the remote end after a state transition
+enum Answer {
+  Enable,
+  Disable,
+}
+
+// which options we agree to perform, and which we let the remote end perform
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct OptionPolicy {
+  local: Vec<bool>,
+  remote: Vec<bool>,
+}
+
+impl OptionPolicy {
+  // refuse everything
+  pub fn new() -> OptionPolicy {
+    OptionPolicy {
+      local: vec![false; 256],
+      remote: vec![false; 256],
+    }
+  }
+
+  // agree to DO requests for the option
+  pub fn local(mut self, option: TelnetOption) -> OptionPolicy {
+    self.local[index(option)] = true;
+    self
+  }
+
+  // agree to WILL requests for the option
+  pub fn remote(mut self, option: TelnetOption) -> OptionPolicy {
+    self.remote[index(option)] = true;
+    self
+  }
+
+  pub fn allows_local(&self, option: TelnetOption) -> bool {
+    self.local[index(option)]
+  }
+
+  pub fn allows_remote(&self, option: TelnetOption) -> bool {
+    self.remote[index(option)]
+  }
+}
+
+impl Default for OptionPolicy {
+  fn default() -> OptionPolicy {
+    OptionPolicy::new()
+  }
+}
+
+pub struct OptionTable {
+  states: Vec<OptionState>,
+  policy: OptionPolicy,
+  events: VecDeque<TelnetEvent>,
+  clock: Box<dyn Clock + Send>,
+  timeout: Option<Duration>,
+  requests: Vec<Request>,
+  timing_marks: VecDeque<(TimingMarkToken, Instant)>,
+  next_timing_mark: u64,
+}
+
+impl OptionTable {
+  pub fn new() -> OptionTable {
+    OptionTable::with_policy(OptionPolicy::new())
+  }
+
+  pub fn with_policy(policy: OptionPolicy) -> OptionTable {
+    OptionTable {
+      states: vec![OptionState::default(); 256],
+      policy,
+      events: VecDeque::new(),
+      clock: Box::new(SystemClock),
+      timeout: None,
+      requests: Vec::new(),
+      timing_marks: VecDeque::new(),
+      next_timing_mark: 0,
+    }
+  }
+
+  // give up waiting for an answer to our requests after the duration
+  pub fn timeout(mut self, timeout: Duration) -> OptionTable {
+    self.timeout = Some(timeout);
+    self
+  }
+
+  pub fn clock<C: Clock + Send + 'static>(mut self, clock: C) -> OptionTable {
+    self.clock = Box::new(clock);
+    self
+  }
+
+  pub fn now(&self) -> Instant {
+    self.clock.now()
+  }
+
+  pub fn policy(&self) -> &OptionPolicy {
+    &self.policy
+  }
+
+  pub fn set_policy(&mut self, policy: OptionPolicy) {
+    self.policy = policy;
+  }
+
+  pub fn state(&self, option: TelnetOption) -> OptionState {
+    self.states[index(option)]
+  }
+
+  pub fn local_enabled(&self, option: TelnetOption) -> bool {
+    self.states[index(option)].us == QState::Yes
+  }
+
+  pub fn remote_enabled(&self, option: TelnetOption) -> bool {
+    self.states[index(option)].him == QState::Yes
+  }
+
+  // ask to start performing the option ourselves, returns the WILL to send if any
+  pub fn enable_local(&mut self, option: TelnetOption) -> Option<TelnetEvent> {
+    let state = &mut self.states[index(option)];
+    let answer = ask_enable(&mut state.us, &mut state.usq);
+    let request = local_answer(option, answer);
+    self.track(option, Side::Local, request.is_some());
+    request
+  }
+
+  // ask to stop performing the option ourselves, returns the WONT to send if any
+  pub fn disable_local(&mut self, option: TelnetOption) -> Option<TelnetEvent> {
+    let state = &mut self.states[index(option)];
+    let answer = ask_disable(&mut state.us, &mut state.usq);
+    let request = local_answer(option, answer);
+    self.track(option, Side::Local, request.is_some());
+    request
+  }
+
+  // ask the remote end to start performing the option, returns the DO to send if any
+  pub fn enable_remote(&mut self, option: TelnetOption) -> Option<TelnetEvent> {
+    let state = &mut self.states[index(option)];
+    let answer = ask_enable(&mut state.him, &mut state.himq);
+    let request = remote_answer(option, answer);
+    self.track(option, Side::Remote, request.is_some());
+    request
+  }
+
+  // ask the remote end to stop performing the option, returns the DONT to send if any
+  pub fn disable_remote(&mut self, option: TelnetOption) -> Option<TelnetEvent> {
+    let state = &mut self.states[index(option)];
+    let answer = ask_disable(&mut state.him, &mut state.himq);
+    let request = remote_answer(option, answer);
+    self.track(option, Side::Remote, request.is_some());
+    request
+  }
+
+  // send DO TIMING-MARK, the remote end answers once it has processed everything before it.
+  // Timing marks are never in effect, so they bypass the option states.
+  pub fn timing_mark(&mut self) -> (TimingMarkToken, TelnetEvent) {
+    let token = TimingMarkToken(self.next_timing_mark);
+    self.next_timing_mark += 1;
+    self.timing_marks.push_back((token, self.clock.now()));
+    (token, TelnetEvent::Do(TelnetOption::TimingMark))
+  }
+
+  // feed a received negotiation verb through the table, returns the reply to send if any
+  pub fn receive(&mut self, event: &TelnetEvent) -> Option<TelnetEvent> {
+    let option = match *event {
+      TelnetEvent::Do(option)
+      | TelnetEvent::Dont(option)
+      | TelnetEvent::Will(option)
+      | TelnetEvent::Wont(option) => option,
+      _ => return None,
+    };
+    if option == TelnetOption::TimingMark {
+      return self.receive_timing_mark(event);
+    }
+    let before = self.states[index(option)];
+    let side = match *event {
+      TelnetEvent::Do(_) | TelnetEvent::Dont(_) => Side::Local,
+      _ => Side::Remote,
+    };
+    let reply = match *event {
+      TelnetEvent::Do(_) => {
+        let agree = self.policy.allows_local(option);
+        let state = &mut self.states[index(option)];
+        let answer = receive_enable(&mut state.us, &mut state.usq, agree);
+        local_answer(option, answer)
+      },
+      TelnetEvent::Dont(_) => {
+        let state = &mut self.states[index(option)];
+        let answer = receive_disable(&mut state.us, &mut state.usq);
+        local_answer(option, answer)
+      },
+      TelnetEvent::Will(_) => {
+        let agree = self.policy.allows_remote(option);
+        let state = &mut self.states[index(option)];
+        let answer = receive_enable(&mut state.him, &mut state.himq, agree);
+        remote_answer(option, answer)
+      },
+      _ => {
+        let state = &mut self.states[index(option)];
+        let answer = receive_disable(&mut state.him, &mut state.himq);
+        remote_answer(option, answer)
+      },
+    };
+    self.notify(option, before);
+    self.track(option, side, reply.is_some());
+    reply
+  }
+
+  // report every request whose deadline has passed. The option is left waiting so a late
+  // answer still completes the negotiation.
+  pub fn poll_timeouts(&mut self, now: Instant) -> Vec<TelnetEvent> {
+    let mut expired = Vec::new();
+    self.requests.retain(|request| {
+      if request.deadline <= now {
+        expired.push(TelnetEvent::NegotiationTimeout { option: request.option, side: request.side });
+        false
+      } else {
+        true
+      }
+    });
+    expired
+  }
+
+  // the options in effect as a STATUS IS list: WILL for the options we perform, DO for the
+  // options the remote end performs
+  pub fn status(&self) -> Vec<TelnetEvent> {
+    let mut entries = Vec::new();
+    for (byte, state) in self.states.iter().enumerate() {
+      let option = TelnetOption::from(byte as u8);
+      if state.us == QState::Yes {
+        entries.push(TelnetEvent::Will(option));
+      }
+      if state.him == QState::Yes {
+        entries.push(TelnetEvent::Do(option));
+      }
+    }
+    entries
+  }
+
+  // the next OptionEnabled or OptionDisabled event, in the order negotiation completed
+  pub fn poll_event(&mut self) -> Option<TelnetEvent> {
+    self.events.pop_front()
+  }
+
+  // true when the verb concerns an option the policy refuses and that is not being negotiated,
+  // so the automatic reply is all that is needed. Timing marks are always handled here.
+  pub fn rejects(&self, event: &TelnetEvent) -> bool {
+    match *event {
+      TelnetEvent::Do(TelnetOption::TimingMark)
+      | TelnetEvent::Dont(TelnetOption::TimingMark)
+      | TelnetEvent::Will(TelnetOption::TimingMark)
+      | TelnetEvent::Wont(TelnetOption::TimingMark) => true,
+      TelnetEvent::Do(option) | TelnetEvent::Dont(option) => {
+        !self.policy.allows_local(option) && self.states[index(option)].us == QState::No
+      },
+      TelnetEvent::Will(option) | TelnetEvent::Wont(option) => {
+        !self.policy.allows_remote(option) && self.states[index(option)].him == QState::No
+      },
+      _ => false,
+    }
+  }
+
+  fn receive_timing_mark(&mut self, event: &TelnetEvent) -> Option<TelnetEvent> {
+    match *event {
+      // every input before the mark has been processed by the time it is decoded
+      TelnetEvent::Do(_) => {
+        if self.policy.allows_local(TelnetOption::TimingMark) {
+          Some(TelnetEvent::Will(TelnetOption::TimingMark))
+        } else {
+          Some(TelnetEvent::Wont(TelnetOption::TimingMark))
+        }
+      },
+      TelnetEvent::Will(_) | TelnetEvent::Wont(_) => {
+        match self.timing_marks.pop_front() {
+          Some((token, sent)) => {
+            let elapsed = self.clock.now().duration_since(sent);
+            self.events.push_back(TelnetEvent::TimingMark { token, elapsed });
+            None
+          },
+          None => match *event {
+            TelnetEvent::Will(_) => Some(TelnetEvent::Dont(TelnetOption::TimingMark)),
+            _ => None,
+          },
+        }
+      },
+      _ => None,
+    }
+  }
+
+  // start the timer when a request is sent, and stop it once the option settles
+  fn track(&mut self, option: TelnetOption, side: Side, sent: bool) {
+    let state = self.states[index(option)];
+    let waiting = match side {
+      Side::Local => pending(state.us),
+      Side::Remote => pending(state.him),
+    };
+    if waiting && !sent {
+      return;
+    }
+    self.requests.retain(|request| request.option != option || request.side != side);
+    if let (true, Some(timeout)) = (waiting, self.timeout) {
+      let deadline = self.clock.now() + timeout;
+      self.requests.push(Request { option, side, deadline });
+    }
+  }
+
+  // queue an event for each side whose option came into or went out of effect
+  fn notify(&mut self, option: TelnetOption, before: OptionState) {
+    let after = self.states[index(option)];
+    let sides = [
+      (Side::Local, before.us, after.us),
+      (Side::Remote, before.him, after.him),
+    ];
+    for &(side, before, after) in sides.iter() {
+      match (in_effect(before), in_effect(after)) {
+        (false, true) => self.events.push_back(TelnetEvent::OptionEnabled { option, side }),
+        (true, false) => self.events.push_back(TelnetEvent::OptionDisabled { option, side }),
+        _ => {},
+      }
+    }
+  }
+}
+
+impl Default for OptionTable {
+  fn default() -> OptionTable {
+    OptionTable::new()
+  }
+}
+
+fn index(option: TelnetOption) -> usize {
+  let byte: u8 = option.into();
+  byte as usize
+}
+
+fn local_answer(option: TelnetOption, answer: Option<Answer>) -> Option<TelnetEvent> {
+  match answer {
+    Some(Answer::Enable) => Some(TelnetEvent::Will(option)),
+    Some(Answer::Disable) => Some(TelnetEvent::Wont(option)),
+    None => None,
+  }
+}
+
+fn remote_answer(option: TelnetOption, answer: Option<Answer>) -> Option<TelnetEvent> {
+  match answer {
+    Some(Answer::Enable) => Some(TelnetEvent::Do(option)),
+    Some(Answer::Disable) => Some(TelnetEvent::Dont(option)),
+    None => None,
+  }
+}
+
+// the remote end asked for the option to be enabled (WILL for him, DO for us)
+fn receive_enable(state: &mut QState, queue: &mut QueueState, agree: bool) -> Option<Answer> {
+  match (*state, *queue) {
+    (QState::No, _) => {
+      if agree {
+        *state = QState::Yes;
+        Some(Answer::Enable)
+      } else {
+        Some(Answer::Disable)
+      }
+    },
+    (QState::Yes, _) => None, // already enabled, this is an acknowledgement
+    (QState::WantNo, QueueState::Empty) => {
+      // error: our disable request was answered with an enable
+      *state = QState::No;
+      None
+    },
+    (QState::WantNo, QueueState::Opposite) => {
+      // error: our disable request was answered with an enable
+      *state = QState::Yes;
+      *queue = QueueState::Empty;
+      None
+    },
+    (QState::WantYes, QueueState::Empty) => {
+      *state = QState::Yes;
+      None
+    },
+    (QState::WantYes, QueueState::Opposite) => {
+      *state = QState::WantNo;
+      *queue = QueueState::Empty;
+      Some(Answer::Disable)
+    },
+  }
+}
+
+// the remote end asked for the option to be disabled (WONT for him, DONT for us)
+fn receive_disable(state: &mut QState, queue: &mut QueueState) -> Option<Answer> {
+  match (*state, *queue) {
+    (QState::No, _) => None, // already disabled, this is an acknowledgement
+    (QState::Yes, _) => {
+      *state = QState::No;
+      Some(Answer::Disable)
+    },
+    (QState::WantNo, QueueState::Empty) => {
+      *state = QState::No;
+      None
+    },
+    (QState::WantNo, QueueState::Opposite) => {
+      *state = QState::WantYes;
+      *queue = QueueState::Empty;
+      Some(Answer::Enable)
+    },
+    (QState::WantYes, _) => {
+      // our enable request was refused
+      *state = QState::No;
+      *queue = QueueState::Empty;
+      None
+    },
+  }
+}
+
+// we want the option enabled
+fn ask_enable(state: &mut QState, queue: &mut QueueState) -> Option<Answer> {
+  match (*state, *queue) {
+    (QState::No, _) => {
+      *state = QState::WantYes;
+      Some(Answer::Enable)
+    },
+    (QState::Yes, _) => None, // already enabled
+    (QState::WantNo, QueueState::Empty) => {
+      // enable once the pending disable is answered
+      *queue = QueueState::Opposite;
+      None
+    },
+    (QState::WantNo, QueueState::Opposite) => None, // already queued
+    (QState::WantYes, QueueState::Empty) => None, // already negotiating
+    (QState::WantYes, QueueState::Opposite) => {
+      // cancel the queued disable
+      *queue = QueueState::Empty;
+      None
+    },
+  }
+}
+
+// we want the option disabled
+fn ask_disable(state: &mut QState, queue: &mut QueueState) -> Option<Answer> {
+  match (*state, *queue) {
+    (QState::No, _) => None, // already disabled
+    (QState::Yes, _) => {
+      *state = QState::WantNo;
+      Some(Answer::Disable)
+    },
+    (QState::WantNo, QueueState::Empty) => None, // already negotiating
+    (QState::WantNo, QueueState::Opposite) => {
+      // cancel the queued enable
+      *queue = QueueState::Empty;
+      None
+    },
+    (QState::WantYes, QueueState::Empty) => {
+      // disable once the pending enable is answered
+      *queue = QueueState::Opposite;
+      None
+    },
+    (QState::WantYes, QueueState::Opposite) => None, // already queued
+  }
+}
@@ -1,7 +1,7 @@
 use std::convert::From;
 use crate::consts::*;
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
 pub enum TelnetOption {
     BinaryTransmission              , // switch to utf-8
     Echo                            , // echo back everything
@@ -39,6 +39,14 @@ pub enum TelnetOption {
     RemoteFlowControl               ,
     Linemode                        ,
     XDisplayLocation                ,
+    Environ                         ,
+    Authentication                  ,
+    Encrypt                         ,
+    NewEnviron                      ,
+    Tn3270e                         ,
+    Charset                         ,
+    ComPortControl                  ,
+    StartTls                        ,
     ExtendedOptionsList             ,
     Other(u8)                       ,
 }
@@ -82,6 +90,14 @@ impl Into<u8> for TelnetOption {
       TelnetOption::RemoteFlowControl               => REMOTE_FLOW_CONTROL,
       TelnetOption::Linemode                        => LINEMODE,
       TelnetOption::XDisplayLocation                => X_DISPLAY_LOCATION,
+      TelnetOption::Environ                         => ENVIRON,
+      TelnetOption::Authentication                  => AUTHENTICATION,
+      TelnetOption::Encrypt                         => ENCRYPT,
+      TelnetOption::NewEnviron                      => NEW_ENVIRON,
+      TelnetOption::Tn3270e                         => TN3270E,
+      TelnetOption::Charset                         => CHARSET,
+      TelnetOption::ComPortControl                  => COM_PORT_OPTION,
+      TelnetOption::StartTls                        => START_TLS,
       TelnetOption::ExtendedOptionsList             => EXTENDED_OPTIONS_LIST,
       TelnetOption::Other(byte)                     => byte,
     }
@@ -127,6 +143,14 @@ impl From<u8> for TelnetOption {
       REMOTE_FLOW_CONTROL                => TelnetOption::RemoteFlowControl,
       LINEMODE                           => TelnetOption::Linemode,
       X_DISPLAY_LOCATION                 => TelnetOption::XDisplayLocation,
+      ENVIRON                            => TelnetOption::Environ,
+      AUTHENTICATION                     => TelnetOption::Authentication,
+      ENCRYPT                            => TelnetOption::Encrypt,
+      NEW_ENVIRON                        => TelnetOption::NewEnviron,
+      TN3270E                            => TelnetOption::Tn3270e,
+      CHARSET                            => TelnetOption::Charset,
+      COM_PORT_OPTION                    => TelnetOption::ComPortControl,
+      START_TLS                          => TelnetOption::StartTls,
       EXTENDED_OPTIONS_LIST              => TelnetOption::ExtendedOptionsList,
       _                                  => TelnetOption::Other(byte),
     }
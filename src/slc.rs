@@ -0,0 +1,329 @@
+use std::convert::From;
+use crate::consts::*;
+use crate::error::*;
+
+// RFC 1184 special line characters
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub enum SlcFunction {
+    Synch         , // synchronize
+    Brk           , // break
+    Ip            , // interrupt process
+    Ao            , // abort output
+    Ayt           , // are you there
+    Eor           , // end of record
+    Abort         ,
+    Eof           ,
+    Susp          , // suspend
+    Ec            , // erase character
+    El            , // erase line
+    Ew            , // erase word
+    Rp            , // reprint line
+    Lnext         , // literal next
+    Xon           ,
+    Xoff          ,
+    Forw1         , // forwarding characters
+    Forw2         ,
+    Mcl           , // move cursor left
+    Mcr           , // move cursor right
+    Mcwl          , // move cursor one word left
+    Mcwr          , // move cursor one word right
+    Mcbol         , // move cursor to beginning of line
+    Mceol         , // move cursor to end of line
+    Insrt         , // insert mode
+    Over          , // overstrike mode
+    Ecr           , // erase character to the right
+    Ewr           , // erase word to the right
+    Ebol          , // erase to beginning of line
+    Eeol          , // erase to end of line
+    Other(u8)     ,
+}
+
+impl From<SlcFunction> for u8 {
+  fn from(function: SlcFunction) -> u8 {
+    match function {
+      SlcFunction::Synch => SLC_SYNCH,
+      SlcFunction::Brk   => SLC_BRK,
+      SlcFunction::Ip    => SLC_IP,
+      SlcFunction::Ao    => SLC_AO,
+      SlcFunction::Ayt   => SLC_AYT,
+      SlcFunction::Eor   => SLC_EOR,
+      SlcFunction::Abort => SLC_ABORT,
+      SlcFunction::Eof   => SLC_EOF,
+      SlcFunction::Susp  => SLC_SUSP,
+      SlcFunction::Ec    => SLC_EC,
+      SlcFunction::El    => SLC_EL,
+      SlcFunction::Ew    => SLC_EW,
+      SlcFunction::Rp    => SLC_RP,
+      SlcFunction::Lnext => SLC_LNEXT,
+      SlcFunction::Xon   => SLC_XON,
+      SlcFunction::Xoff  => SLC_XOFF,
+      SlcFunction::Forw1 => SLC_FORW1,
+      SlcFunction::Forw2 => SLC_FORW2,
+      SlcFunction::Mcl   => SLC_MCL,
+      SlcFunction::Mcr   => SLC_MCR,
+      SlcFunction::Mcwl  => SLC_MCWL,
+      SlcFunction::Mcwr  => SLC_MCWR,
+      SlcFunction::Mcbol => SLC_MCBOL,
+      SlcFunction::Mceol => SLC_MCEOL,
+      SlcFunction::Insrt => SLC_INSRT,
+      SlcFunction::Over  => SLC_OVER,
+      SlcFunction::Ecr   => SLC_ECR,
+      SlcFunction::Ewr   => SLC_EWR,
+      SlcFunction::Ebol  => SLC_EBOL,
+      SlcFunction::Eeol  => SLC_EEOL,
+      SlcFunction::Other(code) => code,
+    }
+  }
+}
+
+impl From<u8> for SlcFunction {
+  fn from(code: u8) -> SlcFunction {
+    match code {
+      SLC_SYNCH => SlcFunction::Synch,
+      SLC_BRK   => SlcFunction::Brk,
+      SLC_IP    => SlcFunction::Ip,
+      SLC_AO    => SlcFunction::Ao,
+      SLC_AYT   => SlcFunction::Ayt,
+      SLC_EOR   => SlcFunction::Eor,
+      SLC_ABORT => SlcFunction::Abort,
+      SLC_EOF   => SlcFunction::Eof,
+      SLC_SUSP  => SlcFunction::Susp,
+      SLC_EC    => SlcFunction::Ec,
+      SLC_EL    => SlcFunction::El,
+      SLC_EW    => SlcFunction::Ew,
+      SLC_RP    => SlcFunction::Rp,
+      SLC_LNEXT => SlcFunction::Lnext,
+      SLC_XON   => SlcFunction::Xon,
+      SLC_XOFF  => SlcFunction::Xoff,
+      SLC_FORW1 => SlcFunction::Forw1,
+      SLC_FORW2 => SlcFunction::Forw2,
+      SLC_MCL   => SlcFunction::Mcl,
+      SLC_MCR   => SlcFunction::Mcr,
+      SLC_MCWL  => SlcFunction::Mcwl,
+      SLC_MCWR  => SlcFunction::Mcwr,
+      SLC_MCBOL => SlcFunction::Mcbol,
+      SLC_MCEOL => SlcFunction::Mceol,
+      SLC_INSRT => SlcFunction::Insrt,
+      SLC_OVER  => SlcFunction::Over,
+      SLC_ECR   => SlcFunction::Ecr,
+      SLC_EWR   => SlcFunction::Ewr,
+      SLC_EBOL  => SlcFunction::Ebol,
+      SLC_EEOL  => SlcFunction::Eeol,
+      _ => SlcFunction::Other(code),
+    }
+  }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SlcLevel {
+  NoSupport,
+  CantChange,
+  Value,
+  Default,
+}
+
+impl SlcLevel {
+  pub fn from_bits(bits: u8) -> SlcLevel {
+    match bits & SLC_LEVELBITS {
+      SLC_NOSUPPORT => SlcLevel::NoSupport,
+      SLC_CANTCHANGE => SlcLevel::CantChange,
+      SLC_VALUE => SlcLevel::Value,
+      _ => SlcLevel::Default,
+    }
+  }
+
+  pub fn bits(self) -> u8 {
+    match self {
+      SlcLevel::NoSupport => SLC_NOSUPPORT,
+      SlcLevel::CantChange => SLC_CANTCHANGE,
+      SlcLevel::Value => SLC_VALUE,
+      SlcLevel::Default => SLC_DEFAULT,
+    }
+  }
+}
+
+// one function, modifier, value triplet of a LINEMODE SLC subnegotiation
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SlcTriplet {
+  pub function: SlcFunction,
+  pub level: SlcLevel,
+  pub ack: bool,
+  pub flush_in: bool,
+  pub flush_out: bool,
+  pub value: u8,
+}
+
+impl SlcTriplet {
+  pub fn new(function: SlcFunction, level: SlcLevel, value: u8) -> SlcTriplet {
+    SlcTriplet { function, level, ack: false, flush_in: false, flush_out: false, value }
+  }
+
+  pub fn parse(bytes: &[u8]) -> Result<Vec<SlcTriplet>, TelnetError> {
+    let triplets = bytes.chunks_exact(3);
+    if !triplets.remainder().is_empty() {
+      return Err(TelnetError::InvalidSubnegotiationSequence);
+    }
+    Ok(triplets.map(|triplet| SlcTriplet {
+      function: triplet[0].into(),
+      level: SlcLevel::from_bits(triplet[1]),
+      ack: triplet[1] & SLC_ACK != 0,
+      flush_in: triplet[1] & SLC_FLUSHIN != 0,
+      flush_out: triplet[1] & SLC_FLUSHOUT != 0,
+      value: triplet[2],
+    }).collect())
+  }
+
+  pub fn modifier(&self) -> u8 {
+    let mut modifier = self.level.bits();
+    if self.ack { modifier |= SLC_ACK; }
+    if self.flush_in { modifier |= SLC_FLUSHIN; }
+    if self.flush_out { modifier |= SLC_FLUSHOUT; }
+    modifier
+  }
+
+  pub fn bytes(&self) -> [u8; 3] {
+    [self.function.into(), self.modifier(), self.value]
+  }
+
+  fn flush(self, flush_in: bool, flush_out: bool) -> SlcTriplet {
+    SlcTriplet { flush_in, flush_out, ..self }
+  }
+
+  fn acknowledged(self) -> SlcTriplet {
+    SlcTriplet { ack: true, ..self }
+  }
+}
+
+// the characters both ends agreed on for each of the 30 functions
+#[derive(Debug, Clone)]
+pub struct SlcTable {
+  current: Vec<SlcTriplet>,
+  defaults: Vec<SlcTriplet>,
+}
+
+impl Default for SlcTable {
+  fn default() -> SlcTable {
+    SlcTable::new()
+  }
+}
+
+impl SlcTable {
+  // the usual BSD terminal characters, everything else is not supported
+  pub fn new() -> SlcTable {
+    let mut defaults: Vec<SlcTriplet> = (SLC_SYNCH..=SLC_EEOL)
+      .map(|code| SlcTriplet::new(code.into(), SlcLevel::NoSupport, 0))
+      .collect();
+    let values = [
+      SlcTriplet::new(SlcFunction::Ip, SlcLevel::Value, 0x03).flush(true, true),
+      SlcTriplet::new(SlcFunction::Ao, SlcLevel::Value, 0x0f).flush(false, true),
+      SlcTriplet::new(SlcFunction::Ayt, SlcLevel::Value, 0x14),
+      SlcTriplet::new(SlcFunction::Abort, SlcLevel::Value, 0x1c).flush(true, true),
+      SlcTriplet::new(SlcFunction::Eof, SlcLevel::Value, 0x04),
+      SlcTriplet::new(SlcFunction::Susp, SlcLevel::Value, 0x1a).flush(true, false),
+      SlcTriplet::new(SlcFunction::Ec, SlcLevel::Value, 0x7f),
+      SlcTriplet::new(SlcFunction::El, SlcLevel::Value, 0x15),
+      SlcTriplet::new(SlcFunction::Ew, SlcLevel::Value, 0x17),
+      SlcTriplet::new(SlcFunction::Rp, SlcLevel::Value, 0x12),
+      SlcTriplet::new(SlcFunction::Lnext, SlcLevel::Value, 0x16),
+      SlcTriplet::new(SlcFunction::Xon, SlcLevel::Value, 0x11),
+      SlcTriplet::new(SlcFunction::Xoff, SlcLevel::Value, 0x13),
+    ];
+    for triplet in values.iter() {
+      if let Some(index) = SlcTable::index(triplet.function) {
+        defaults[index] = *triplet;
+      }
+    }
+    SlcTable { current: defaults.clone(), defaults }
+  }
+
+  pub fn get(&self, function: SlcFunction) -> Option<&SlcTriplet> {
+    SlcTable::index(function).map(|index| &self.current[index])
+  }
+
+  // the character for a function, None when the function is not supported
+  pub fn value(&self, function: SlcFunction) -> Option<u8> {
+    match self.get(function) {
+      Some(triplet) if triplet.level != SlcLevel::NoSupport => Some(triplet.value),
+      _ => None,
+    }
+  }
+
+  pub fn set(&mut self, triplet: SlcTriplet) {
+    if let Some(index) = SlcTable::index(triplet.function) {
+      self.current[index] = SlcTriplet { ack: false, ..triplet };
+    }
+  }
+
+  // the whole table, as sent at the start of LINEMODE or when asked for
+  pub fn triplets(&self) -> Vec<SlcTriplet> {
+    self.current.clone()
+  }
+
+  // apply the triplets the remote end sent, returns the triplets to send back
+  pub fn receive(&mut self, triplets: &[SlcTriplet]) -> Vec<SlcTriplet> {
+    let mut replies = Vec::new();
+    for triplet in triplets {
+      if triplet.function == SlcFunction::Other(0) {
+        // 0 SLC_DEFAULT 0 asks us to reset to our defaults, 0 SLC_VALUE 0 asks for our table
+        match triplet.level {
+          SlcLevel::Default => {
+            self.current = self.defaults.clone();
+            replies.extend(self.triplets());
+          },
+          SlcLevel::Value => replies.extend(self.triplets()),
+          _ => {},
+        }
+        continue;
+      }
+      let index = match SlcTable::index(triplet.function) {
+        Some(index) => index,
+        None => {
+          if triplet.level != SlcLevel::NoSupport {
+            replies.push(SlcTriplet::new(triplet.function, SlcLevel::NoSupport, 0));
+          }
+          continue;
+        },
+      };
+      let current = self.current[index];
+
+      // already agreed, this is usually the answer to one of our own triplets
+      if triplet.level == current.level && triplet.value == current.value {
+        continue;
+      }
+      // the remote end accepted a value, there is nothing left to answer
+      if triplet.ack {
+        self.current[index] = SlcTriplet { ack: false, ..*triplet };
+        continue;
+      }
+      match triplet.level {
+        SlcLevel::Default => {
+          self.current[index] = self.defaults[index];
+          replies.push(self.current[index]);
+        },
+        SlcLevel::NoSupport => {
+          self.current[index] = SlcTriplet { value: 0, ..*triplet };
+          replies.push(self.current[index].acknowledged());
+        },
+        SlcLevel::Value | SlcLevel::CantChange => {
+          if current.level == SlcLevel::CantChange {
+            // we can't take their value, insist on ours
+            replies.push(current);
+          } else {
+            self.current[index] = *triplet;
+            replies.push(triplet.acknowledged());
+          }
+        },
+      }
+    }
+    replies
+  }
+
+  fn index(function: SlcFunction) -> Option<usize> {
+    let code: u8 = function.into();
+    if (SLC_SYNCH..=SLC_EEOL).contains(&code) {
+      Some((code - SLC_SYNCH) as usize)
+    } else {
+      None
+    }
+  }
+}
@@ -1,8 +1,1217 @@
 use crate::option::{ TelnetOption };
+use crate::event::*;
+use crate::error::*;
+use crate::consts::*;
+use crate::slc::*;
+use std::fmt;
 
 #[derive(Debug,PartialEq)]
 pub enum SubnegotiationType {
   NegotiateAboutWindowSize(u16, u16),
+  Status(Status),
+  ExtendedOptionsList(ExtendedOptionsList),
+  TerminalType(TerminalType),
+  TerminalSpeed(TerminalSpeed),
+  Linemode(Linemode),
+  XDisplayLocation(XDisplayLocation),
+  Environ(Environ, EnvironDialect),
+  NewEnviron(Environ),
+  Charset(Charset),
+  ComPortControl(ComPortControl),
+  Authentication(Authentication),
+  Encrypt(Encrypt),
+  Tn3270e(Tn3270e),
+  // RFC 779, where the sender's terminal is, as free text
+  SendLocation(String),
+  // IAC SB START_TLS FOLLOWS IAC SE, the TLS handshake starts right after it
+  StartTlsFollows,
   // TODO: Implement more
   Other(TelnetOption, Vec<u8>),
 }
+
+impl SubnegotiationType {
+  // interpret the unescaped bytes between IAC SB <option> and IAC SE
+  pub fn parse(option: u8, bytes: Vec<u8>) -> Result<SubnegotiationType, TelnetError> {
+    match option {
+      NEGOTIATE_ABOUT_WINDOW_SIZE => {
+        // Assert that there are 4 bytes in the vec
+        match bytes.len() {
+          4 => Ok(SubnegotiationType::NegotiateAboutWindowSize(
+            ((bytes[0] as u16) << 8) | (bytes[1] as u16), // big endian width
+            ((bytes[2] as u16) << 8) | (bytes[3] as u16), // big endian height
+          )),
+          _ => Err(TelnetError::InvalidSubnegotiationSequence), // Invalid
+        }
+      },
+      STATUS => Ok(SubnegotiationType::Status(Status::parse(&bytes)?)),
+      TERMINAL_TYPE => Ok(SubnegotiationType::TerminalType(TerminalType::parse(&bytes)?)),
+      TERMINAL_SPEED => Ok(SubnegotiationType::TerminalSpeed(TerminalSpeed::parse(&bytes)?)),
+      LINEMODE => Ok(SubnegotiationType::Linemode(Linemode::parse(&bytes)?)),
+      X_DISPLAY_LOCATION => Ok(SubnegotiationType::XDisplayLocation(XDisplayLocation::parse(&bytes)?)),
+      ENVIRON => {
+        let dialect = EnvironDialect::detect(&bytes);
+        Ok(SubnegotiationType::Environ(Environ::parse_dialect(&bytes, dialect)?, dialect))
+      },
+      NEW_ENVIRON => Ok(SubnegotiationType::NewEnviron(Environ::parse(&bytes)?)),
+      CHARSET => Ok(SubnegotiationType::Charset(Charset::parse(&bytes)?)),
+      AUTHENTICATION => Ok(SubnegotiationType::Authentication(Authentication::parse(&bytes)?)),
+      ENCRYPT => Ok(SubnegotiationType::Encrypt(Encrypt::parse(&bytes)?)),
+      SEND_LOCATION => Ok(SubnegotiationType::SendLocation(String::from_utf8_lossy(&bytes).to_string())),
+      TN3270E => Ok(SubnegotiationType::Tn3270e(Tn3270e::parse(&bytes)?)),
+      START_TLS => match *bytes {
+        [START_TLS_FOLLOWS] => Ok(SubnegotiationType::StartTlsFollows),
+        _ => Err(TelnetError::InvalidSubnegotiationSequence),
+      },
+      COM_PORT_OPTION => Ok(SubnegotiationType::ComPortControl(ComPortControl::parse(&bytes)?)),
+      EXTENDED_OPTIONS_LIST => Ok(SubnegotiationType::ExtendedOptionsList(ExtendedOptionsList::parse(&bytes)?)),
+      _ => Ok(SubnegotiationType::Other(option.into(), bytes)),
+    }
+  }
+
+  pub fn option(&self) -> TelnetOption {
+    match self {
+      SubnegotiationType::NegotiateAboutWindowSize(_, _) => TelnetOption::NegotiateAboutWindowSize,
+      SubnegotiationType::Status(_) => TelnetOption::Status,
+      SubnegotiationType::ExtendedOptionsList(_) => TelnetOption::ExtendedOptionsList,
+      SubnegotiationType::TerminalType(_) => TelnetOption::TerminalType,
+      SubnegotiationType::TerminalSpeed(_) => TelnetOption::TerminalSpeed,
+      SubnegotiationType::Linemode(_) => TelnetOption::Linemode,
+      SubnegotiationType::XDisplayLocation(_) => TelnetOption::XDisplayLocation,
+      SubnegotiationType::Environ(_, _) => TelnetOption::Environ,
+      SubnegotiationType::NewEnviron(_) => TelnetOption::NewEnviron,
+      SubnegotiationType::Charset(_) => TelnetOption::Charset,
+      SubnegotiationType::ComPortControl(_) => TelnetOption::ComPortControl,
+      SubnegotiationType::Authentication(_) => TelnetOption::Authentication,
+      SubnegotiationType::Encrypt(_) => TelnetOption::Encrypt,
+      SubnegotiationType::Tn3270e(_) => TelnetOption::Tn3270e,
+      SubnegotiationType::SendLocation(_) => TelnetOption::SendLocation,
+      SubnegotiationType::StartTlsFollows => TelnetOption::StartTls,
+      SubnegotiationType::Other(option, _) => *option,
+    }
+  }
+
+  // the unescaped bytes that go between IAC SB <option> and IAC SE
+  pub fn payload(&self) -> Vec<u8> {
+    match self {
+      SubnegotiationType::NegotiateAboutWindowSize(width, height) => {
+        let mut bytes = Vec::with_capacity(4);
+        bytes.extend_from_slice(&width.to_be_bytes());
+        bytes.extend_from_slice(&height.to_be_bytes());
+        bytes
+      },
+      SubnegotiationType::Status(status) => status.payload(),
+      SubnegotiationType::ExtendedOptionsList(list) => list.payload(),
+      SubnegotiationType::TerminalType(terminal_type) => terminal_type.payload(),
+      SubnegotiationType::TerminalSpeed(terminal_speed) => terminal_speed.payload(),
+      SubnegotiationType::Linemode(linemode) => linemode.payload(),
+      SubnegotiationType::XDisplayLocation(location) => location.payload(),
+      SubnegotiationType::Environ(environ, dialect) => environ.payload_dialect(*dialect),
+      SubnegotiationType::NewEnviron(environ) => environ.payload(),
+      SubnegotiationType::Charset(charset) => charset.payload(),
+      SubnegotiationType::ComPortControl(control) => control.payload(),
+      SubnegotiationType::Authentication(authentication) => authentication.payload(),
+      SubnegotiationType::Encrypt(encrypt) => encrypt.payload(),
+      SubnegotiationType::Tn3270e(tn3270e) => tn3270e.payload(),
+      SubnegotiationType::SendLocation(location) => location.as_bytes().to_vec(),
+      SubnegotiationType::StartTlsFollows => vec![START_TLS_FOLLOWS],
+      SubnegotiationType::Other(_, bytes) => bytes.clone(),
+    }
+  }
+}
+
+// RFC 859, IS lists the WILL and DO options in effect for the sender followed by the
+// parameters of any subnegotiated options as SB <option> <parameters> SE
+#[derive(Debug,PartialEq)]
+pub enum Status {
+  Send,
+  Is(Vec<TelnetEvent>),
+}
+
+impl Status {
+  pub fn parse(bytes: &[u8]) -> Result<Status, TelnetError> {
+    match bytes.split_first() {
+      Some((&SEND, [])) => Ok(Status::Send),
+      Some((&IS, rest)) => {
+        let mut entries = Vec::new();
+        let mut x = 0;
+        while x < rest.len() {
+          if x + 1 >= rest.len() {
+            return Err(TelnetError::InvalidSubnegotiationSequence);
+          }
+          let option = rest[x + 1];
+          x += 2;
+          match rest[x - 2] {
+            WILL => entries.push(TelnetEvent::Will(option.into())),
+            DO => entries.push(TelnetEvent::Do(option.into())),
+            WONT => entries.push(TelnetEvent::Wont(option.into())),
+            DONT => entries.push(TelnetEvent::Dont(option.into())),
+            SUBNEGOTIATION => {
+              // parameters end at a single SE, SE bytes in the parameters are doubled
+              let mut parameters = Vec::new();
+              loop {
+                match (rest.get(x), rest.get(x + 1)) {
+                  (Some(&SUBNEGOTIATION_END), Some(&SUBNEGOTIATION_END)) => {
+                    parameters.push(SUBNEGOTIATION_END);
+                    x += 2;
+                  },
+                  (Some(&SUBNEGOTIATION_END), _) => {
+                    x += 1;
+                    break;
+                  },
+                  (Some(&byte), _) => {
+                    parameters.push(byte);
+                    x += 1;
+                  },
+                  (None, _) => return Err(TelnetError::InvalidSubnegotiationSequence),
+                }
+              }
+              let subnegotiation = SubnegotiationType::parse(option, parameters)?;
+              entries.push(TelnetEvent::Subnegotiation(subnegotiation));
+            },
+            _ => return Err(TelnetError::InvalidSubnegotiationSequence),
+          }
+        }
+        Ok(Status::Is(entries))
+      },
+      _ => Err(TelnetError::InvalidSubnegotiationSequence),
+    }
+  }
+
+  pub fn payload(&self) -> Vec<u8> {
+    match self {
+      Status::Send => vec![SEND],
+      Status::Is(entries) => {
+        let mut bytes = vec![IS];
+        for entry in entries {
+          match entry {
+            TelnetEvent::Will(option) => bytes.extend_from_slice(&[WILL, (*option).into()]),
+            TelnetEvent::Do(option) => bytes.extend_from_slice(&[DO, (*option).into()]),
+            TelnetEvent::Wont(option) => bytes.extend_from_slice(&[WONT, (*option).into()]),
+            TelnetEvent::Dont(option) => bytes.extend_from_slice(&[DONT, (*option).into()]),
+            TelnetEvent::Subnegotiation(subnegotiation) => {
+              bytes.push(SUBNEGOTIATION);
+              bytes.push(subnegotiation.option().into());
+              for byte in subnegotiation.payload() {
+                if byte == SUBNEGOTIATION_END {
+                  bytes.push(SUBNEGOTIATION_END);
+                }
+                bytes.push(byte);
+              }
+              bytes.push(SUBNEGOTIATION_END);
+            },
+            _ => {
+              // only negotiation state can be reported
+            },
+          }
+        }
+        bytes
+      },
+    }
+  }
+}
+
+// options 256 and up, negotiated through EXOPL
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub struct ExtendedOption(pub u16);
+
+impl ExtendedOption {
+  // RFC 861 carries the option as a single byte offset from 256
+  pub fn from_code(code: u8) -> ExtendedOption {
+    ExtendedOption(256 + code as u16)
+  }
+
+  pub fn code(self) -> Option<u8> {
+    match self.0 {
+      256..=511 => Some((self.0 - 256) as u8),
+      _ => None,
+    }
+  }
+}
+
+// RFC 861, IAC SB EXOPL <verb> <code> IAC SE
+#[derive(Debug,PartialEq)]
+pub enum ExtendedOptionsList {
+  Do(ExtendedOption),
+  Dont(ExtendedOption),
+  Will(ExtendedOption),
+  Wont(ExtendedOption),
+}
+
+impl ExtendedOptionsList {
+  pub fn parse(bytes: &[u8]) -> Result<ExtendedOptionsList, TelnetError> {
+    match *bytes {
+      [DO, code] => Ok(ExtendedOptionsList::Do(ExtendedOption::from_code(code))),
+      [DONT, code] => Ok(ExtendedOptionsList::Dont(ExtendedOption::from_code(code))),
+      [WILL, code] => Ok(ExtendedOptionsList::Will(ExtendedOption::from_code(code))),
+      [WONT, code] => Ok(ExtendedOptionsList::Wont(ExtendedOption::from_code(code))),
+      _ => Err(TelnetError::InvalidSubnegotiationSequence),
+    }
+  }
+
+  pub fn option(&self) -> ExtendedOption {
+    match *self {
+      ExtendedOptionsList::Do(option)
+      | ExtendedOptionsList::Dont(option)
+      | ExtendedOptionsList::Will(option)
+      | ExtendedOptionsList::Wont(option) => option,
+    }
+  }
+
+  pub fn payload(&self) -> Vec<u8> {
+    let verb = match self {
+      ExtendedOptionsList::Do(_) => DO,
+      ExtendedOptionsList::Dont(_) => DONT,
+      ExtendedOptionsList::Will(_) => WILL,
+      ExtendedOptionsList::Wont(_) => WONT,
+    };
+    // out of range options are rejected by the encoder before this is called
+    vec![verb, self.option().code().unwrap_or(0)]
+  }
+}
+
+// RFC 1091
+#[derive(Debug,PartialEq)]
+pub enum TerminalType {
+  Is(String),
+  Send,
+}
+
+impl TerminalType {
+  pub fn parse(bytes: &[u8]) -> Result<TerminalType, TelnetError> {
+    match bytes.split_first() {
+      Some((&SEND, [])) => Ok(TerminalType::Send),
+      Some((&IS, name)) => Ok(TerminalType::Is(String::from_utf8_lossy(name).to_string())),
+      _ => Err(TelnetError::InvalidSubnegotiationSequence),
+    }
+  }
+
+  pub fn payload(&self) -> Vec<u8> {
+    match self {
+      TerminalType::Is(name) => {
+        let mut bytes = vec![IS];
+        bytes.extend_from_slice(name.as_bytes());
+        bytes
+      },
+      TerminalType::Send => vec![SEND],
+    }
+  }
+}
+
+// RFC 1079, speeds are sent as ASCII "<transmit>,<receive>" in bits per second
+#[derive(Debug,PartialEq)]
+pub enum TerminalSpeed {
+  Is { transmit: u32, receive: u32 },
+  Send,
+}
+
+impl TerminalSpeed {
+  pub fn parse(bytes: &[u8]) -> Result<TerminalSpeed, TelnetError> {
+    match bytes.split_first() {
+      Some((&SEND, [])) => Ok(TerminalSpeed::Send),
+      Some((&IS, speeds)) => {
+        let mut parts = speeds.split(|byte| *byte == b',');
+        match (parts.next(), parts.next(), parts.next()) {
+          (Some(transmit), Some(receive), None) => Ok(TerminalSpeed::Is {
+            transmit: parse_speed(transmit)?,
+            receive: parse_speed(receive)?,
+          }),
+          _ => Err(TelnetError::InvalidTerminalSpeed),
+        }
+      },
+      _ => Err(TelnetError::InvalidSubnegotiationSequence),
+    }
+  }
+
+  pub fn payload(&self) -> Vec<u8> {
+    match self {
+      TerminalSpeed::Is { transmit, receive } => {
+        let mut bytes = vec![IS];
+        bytes.extend_from_slice(format!("{},{}", transmit, receive).as_bytes());
+        bytes
+      },
+      TerminalSpeed::Send => vec![SEND],
+    }
+  }
+}
+
+fn parse_speed(bytes: &[u8]) -> Result<u32, TelnetError> {
+  if bytes.is_empty() || !bytes.iter().all(u8::is_ascii_digit) {
+    return Err(TelnetError::InvalidTerminalSpeed);
+  }
+  String::from_utf8_lossy(bytes).parse().map_err(|_| TelnetError::InvalidTerminalSpeed)
+}
+
+// the LINEMODE MODE mask. With edit set the client sends whole lines, without it every
+// character is sent as it is typed.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct LinemodeMode {
+  pub edit: bool,
+  pub trapsig: bool,
+  pub soft_tab: bool,
+  pub lit_echo: bool,
+  pub ack: bool,
+}
+
+impl LinemodeMode {
+  pub fn from_bits(bits: u8) -> LinemodeMode {
+    LinemodeMode {
+      edit: bits & MODE_EDIT != 0,
+      trapsig: bits & MODE_TRAPSIG != 0,
+      soft_tab: bits & MODE_SOFT_TAB != 0,
+      lit_echo: bits & MODE_LIT_ECHO != 0,
+      ack: bits & MODE_ACK != 0,
+    }
+  }
+
+  pub fn bits(&self) -> u8 {
+    let mut bits = 0;
+    if self.edit { bits |= MODE_EDIT; }
+    if self.trapsig { bits |= MODE_TRAPSIG; }
+    if self.soft_tab { bits |= MODE_SOFT_TAB; }
+    if self.lit_echo { bits |= MODE_LIT_ECHO; }
+    if self.ack { bits |= MODE_ACK; }
+    bits
+  }
+}
+
+// RFC 1184
+#[derive(Debug,PartialEq)]
+pub enum Linemode {
+  Mode(LinemodeMode),
+  Slc(Vec<SlcTriplet>),
+  DoForwardMask(ForwardMask),
+  DontForwardMask,
+  WillForwardMask,
+  WontForwardMask,
+}
+
+impl Linemode {
+  pub fn parse(bytes: &[u8]) -> Result<Linemode, TelnetError> {
+    match bytes.split_first() {
+      Some((&LINEMODE_MODE, &[mask])) => Ok(Linemode::Mode(LinemodeMode::from_bits(mask))),
+      Some((&LINEMODE_SLC, triplets)) => Ok(Linemode::Slc(SlcTriplet::parse(triplets)?)),
+      Some((&DO, mask)) if mask.first() == Some(&LINEMODE_FORWARDMASK) => {
+        Ok(Linemode::DoForwardMask(ForwardMask::parse(&mask[1..])?))
+      },
+      Some((&DONT, &[LINEMODE_FORWARDMASK])) => Ok(Linemode::DontForwardMask),
+      Some((&WILL, &[LINEMODE_FORWARDMASK])) => Ok(Linemode::WillForwardMask),
+      Some((&WONT, &[LINEMODE_FORWARDMASK])) => Ok(Linemode::WontForwardMask),
+      _ => Err(TelnetError::InvalidSubnegotiationSequence),
+    }
+  }
+
+  pub fn payload(&self) -> Vec<u8> {
+    match self {
+      Linemode::Mode(mode) => vec![LINEMODE_MODE, mode.bits()],
+      Linemode::Slc(triplets) => {
+        let mut payload = vec![LINEMODE_SLC];
+        for triplet in triplets {
+          payload.extend_from_slice(&triplet.bytes());
+        }
+        payload
+      },
+      Linemode::DoForwardMask(mask) => {
+        let mut payload = vec![DO, LINEMODE_FORWARDMASK];
+        payload.extend_from_slice(mask.bytes());
+        payload
+      },
+      Linemode::DontForwardMask => vec![DONT, LINEMODE_FORWARDMASK],
+      Linemode::WillForwardMask => vec![WILL, LINEMODE_FORWARDMASK],
+      Linemode::WontForwardMask => vec![WONT, LINEMODE_FORWARDMASK],
+    }
+  }
+}
+
+// one bit per character, the client sends its buffered input as soon as it reads a character
+// in the mask. The first byte covers characters 0 through 7, most significant bit first.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct ForwardMask([u8; 32]);
+
+impl ForwardMask {
+  pub fn new() -> ForwardMask {
+    ForwardMask::default()
+  }
+
+  // a short mask leaves the remaining characters cleared
+  pub fn parse(bytes: &[u8]) -> Result<ForwardMask, TelnetError> {
+    if bytes.len() > 32 {
+      return Err(TelnetError::InvalidSubnegotiationSequence);
+    }
+    let mut mask = ForwardMask::new();
+    mask.0[..bytes.len()].copy_from_slice(bytes);
+    Ok(mask)
+  }
+
+  pub fn insert(&mut self, character: u8) {
+    self.0[(character / 8) as usize] |= 0x80 >> (character % 8);
+  }
+
+  pub fn remove(&mut self, character: u8) {
+    self.0[(character / 8) as usize] &= !(0x80 >> (character % 8));
+  }
+
+  pub fn contains(&self, character: u8) -> bool {
+    self.0[(character / 8) as usize] & (0x80 >> (character % 8)) != 0
+  }
+
+  pub fn bytes(&self) -> &[u8; 32] {
+    &self.0
+  }
+}
+
+// an X display in the "host:display[.screen]" form
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DisplayLocation {
+  pub host: String,
+  pub display: u16,
+  pub screen: Option<u16>,
+}
+
+impl DisplayLocation {
+  pub fn parse(location: &str) -> Result<DisplayLocation, TelnetError> {
+    let split = location.rfind(':').ok_or(TelnetError::InvalidDisplayLocation)?;
+    let (host, number) = (&location[..split], &location[split + 1..]);
+    let (display, screen) = match number.find('.') {
+      Some(dot) => (&number[..dot], Some(&number[dot + 1..])),
+      None => (number, None),
+    };
+    Ok(DisplayLocation {
+      host: host.to_string(),
+      display: parse_display_number(display)?,
+      screen: match screen {
+        Some(screen) => Some(parse_display_number(screen)?),
+        None => None,
+      },
+    })
+  }
+}
+
+impl fmt::Display for DisplayLocation {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}:{}", self.host, self.display)?;
+    if let Some(screen) = self.screen {
+      write!(f, ".{}", screen)?;
+    }
+    Ok(())
+  }
+}
+
+fn parse_display_number(number: &str) -> Result<u16, TelnetError> {
+  if number.is_empty() || !number.bytes().all(|byte| byte.is_ascii_digit()) {
+    return Err(TelnetError::InvalidDisplayLocation);
+  }
+  number.parse().map_err(|_| TelnetError::InvalidDisplayLocation)
+}
+
+// RFC 1096
+#[derive(Debug,PartialEq)]
+pub enum XDisplayLocation {
+  Is(DisplayLocation),
+  Send,
+}
+
+impl XDisplayLocation {
+  pub fn parse(bytes: &[u8]) -> Result<XDisplayLocation, TelnetError> {
+    match bytes.split_first() {
+      Some((&SEND, [])) => Ok(XDisplayLocation::Send),
+      Some((&IS, location)) => {
+        let location = String::from_utf8_lossy(location);
+        Ok(XDisplayLocation::Is(DisplayLocation::parse(&location)?))
+      },
+      _ => Err(TelnetError::InvalidSubnegotiationSequence),
+    }
+  }
+
+  pub fn payload(&self) -> Vec<u8> {
+    match self {
+      XDisplayLocation::Is(location) => {
+        let mut bytes = vec![IS];
+        bytes.extend_from_slice(location.to_string().as_bytes());
+        bytes
+      },
+      XDisplayLocation::Send => vec![SEND],
+    }
+  }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum EnvironVariableType {
+  Var,
+  UserVar,
+}
+
+// a variable without a value is undefined, SEND lists only carry names and an empty name
+// asks for every variable of that type
+#[derive(Debug, PartialEq, Clone)]
+pub struct EnvironVariable {
+  pub kind: EnvironVariableType,
+  pub name: String,
+  pub value: Option<String>,
+}
+
+impl EnvironVariable {
+  pub fn var(name: &str, value: Option<&str>) -> EnvironVariable {
+    EnvironVariable {
+      kind: EnvironVariableType::Var,
+      name: name.to_string(),
+      value: value.map(str::to_string),
+    }
+  }
+
+  pub fn user_var(name: &str, value: Option<&str>) -> EnvironVariable {
+    EnvironVariable {
+      kind: EnvironVariableType::UserVar,
+      name: name.to_string(),
+      value: value.map(str::to_string),
+    }
+  }
+}
+
+// RFC 1408 defines VAR as 0 and VALUE as 1, but the BSD implementations shipped them the
+// other way around. NEW-ENVIRON always uses the RFC 1408 codes.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum EnvironDialect {
+  Rfc1408,
+  Bsd,
+}
+
+impl EnvironDialect {
+  pub fn var(self) -> u8 {
+    match self {
+      EnvironDialect::Rfc1408 => ENVIRON_VAR,
+      EnvironDialect::Bsd => ENVIRON_VALUE,
+    }
+  }
+
+  pub fn value(self) -> u8 {
+    match self {
+      EnvironDialect::Rfc1408 => ENVIRON_VALUE,
+      EnvironDialect::Bsd => ENVIRON_VAR,
+    }
+  }
+
+  // RFC 1571: a well formed list starts with a VAR, so a list starting with 1 comes from a
+  // peer with swapped codes. After a USERVAR the next code is most likely a VALUE.
+  pub fn detect(bytes: &[u8]) -> EnvironDialect {
+    let list = match bytes.split_first() {
+      Some((_, list)) => list,
+      None => return EnvironDialect::Rfc1408,
+    };
+    match list.first() {
+      Some(&ENVIRON_VALUE) => return EnvironDialect::Bsd,
+      Some(&ENVIRON_USERVAR) => {},
+      _ => return EnvironDialect::Rfc1408,
+    }
+    let mut x = 1;
+    while x < list.len() {
+      match list[x] {
+        ENVIRON_ESC => x += 1,
+        ENVIRON_VAR => return EnvironDialect::Bsd,
+        ENVIRON_VALUE => return EnvironDialect::Rfc1408,
+        _ => {},
+      }
+      x += 1;
+    }
+    EnvironDialect::Rfc1408
+  }
+}
+
+// RFC 1572, and RFC 1408 for the old ENVIRON option
+#[derive(Debug,PartialEq)]
+pub enum Environ {
+  Is(Vec<EnvironVariable>),
+  Info(Vec<EnvironVariable>),
+  Send(Vec<EnvironVariable>),
+}
+
+impl Environ {
+  pub fn parse(bytes: &[u8]) -> Result<Environ, TelnetError> {
+    Environ::parse_dialect(bytes, EnvironDialect::Rfc1408)
+  }
+
+  pub fn parse_dialect(bytes: &[u8], dialect: EnvironDialect) -> Result<Environ, TelnetError> {
+    match bytes.split_first() {
+      Some((&IS, list)) => Ok(Environ::Is(parse_environ_variables(list, dialect, true)?)),
+      Some((&INFO, list)) => Ok(Environ::Info(parse_environ_variables(list, dialect, true)?)),
+      Some((&SEND, list)) => Ok(Environ::Send(parse_environ_variables(list, dialect, false)?)),
+      _ => Err(TelnetError::InvalidSubnegotiationSequence),
+    }
+  }
+
+  pub fn payload(&self) -> Vec<u8> {
+    self.payload_dialect(EnvironDialect::Rfc1408)
+  }
+
+  pub fn payload_dialect(&self, dialect: EnvironDialect) -> Vec<u8> {
+    let (command, variables) = match self {
+      Environ::Is(variables) => (IS, variables),
+      Environ::Info(variables) => (INFO, variables),
+      Environ::Send(variables) => (SEND, variables),
+    };
+    let mut bytes = vec![command];
+    for variable in variables {
+      bytes.push(match variable.kind {
+        EnvironVariableType::Var => dialect.var(),
+        EnvironVariableType::UserVar => ENVIRON_USERVAR,
+      });
+      put_environ_escaped(&mut bytes, variable.name.as_bytes());
+      if let Some(value) = &variable.value {
+        bytes.push(dialect.value());
+        put_environ_escaped(&mut bytes, value.as_bytes());
+      }
+    }
+    bytes
+  }
+}
+
+// VAR, VALUE, ESC and USERVAR inside names and values are preceded by ESC
+fn put_environ_escaped(bytes: &mut Vec<u8>, text: &[u8]) {
+  for byte in text {
+    match *byte {
+      ENVIRON_VAR | ENVIRON_VALUE | ENVIRON_ESC | ENVIRON_USERVAR => {
+        bytes.push(ENVIRON_ESC);
+        bytes.push(*byte);
+      },
+      byte => bytes.push(byte),
+    }
+  }
+}
+
+fn parse_environ_variables(bytes: &[u8], dialect: EnvironDialect, values: bool) -> Result<Vec<EnvironVariable>, TelnetError> {
+  let mut variables = Vec::new();
+  // the variable being read, and whether its value has started
+  let mut current: Option<(EnvironVariableType, Vec<u8>, Option<Vec<u8>>)> = None;
+  let mut x = 0;
+
+  while x < bytes.len() {
+    let mut byte = bytes[x];
+    let kind = if byte == dialect.var() {
+      Some(EnvironVariableType::Var)
+    } else if byte == ENVIRON_USERVAR {
+      Some(EnvironVariableType::UserVar)
+    } else {
+      None
+    };
+    match kind {
+      Some(kind) => {
+        variables.extend(current.take().map(environ_variable));
+        current = Some((kind, Vec::new(), None));
+      },
+      None if byte == dialect.value() => {
+        match current.as_mut() {
+          Some((_, _, value @ None)) if values => *value = Some(Vec::new()),
+          _ => return Err(TelnetError::InvalidSubnegotiationSequence),
+        }
+      },
+      None => {
+        if byte == ENVIRON_ESC {
+          x += 1;
+          byte = *bytes.get(x).ok_or(TelnetError::InvalidSubnegotiationSequence)?;
+        }
+        match current.as_mut() {
+          Some((_, _, Some(value))) => value.push(byte),
+          Some((_, name, None)) => name.push(byte),
+          None => return Err(TelnetError::InvalidSubnegotiationSequence),
+        }
+      },
+    }
+    x += 1;
+  }
+
+  variables.extend(current.take().map(environ_variable));
+  Ok(variables)
+}
+
+fn environ_variable((kind, name, value): (EnvironVariableType, Vec<u8>, Option<Vec<u8>>)) -> EnvironVariable {
+  EnvironVariable {
+    kind,
+    name: String::from_utf8_lossy(&name).to_string(),
+    value: value.map(|value| String::from_utf8_lossy(&value).to_string()),
+  }
+}
+
+// RFC 2066
+#[derive(Debug,PartialEq)]
+pub enum Charset {
+  // charsets in order of preference, ttable is the highest TTABLE version the sender accepts
+  Request { ttable: Option<u8>, charsets: Vec<String> },
+  Accepted(String),
+  Rejected,
+  TtableIs(TranslationTable),
+  TtableRejected,
+  TtableAck,
+  TtableNak,
+}
+
+const TTABLE_MARKER: &[u8] = b"[TTABLE]";
+const CHARSET_SEPARATOR: u8 = b';';
+
+impl Charset {
+  pub fn parse(bytes: &[u8]) -> Result<Charset, TelnetError> {
+    match bytes.split_first() {
+      Some((&CHARSET_REQUEST, mut list)) => {
+        let mut ttable = None;
+        if list.starts_with(TTABLE_MARKER) {
+          let version = list.get(TTABLE_MARKER.len()).ok_or(TelnetError::InvalidSubnegotiationSequence)?;
+          ttable = Some(*version);
+          list = &list[TTABLE_MARKER.len() + 1..];
+        }
+        // the first byte is the separator used between the names
+        let (separator, names) = list.split_first().ok_or(TelnetError::InvalidSubnegotiationSequence)?;
+        let charsets = names
+          .split(|byte| byte == separator)
+          .map(charset_name)
+          .collect::<Result<Vec<String>, TelnetError>>()?;
+        Ok(Charset::Request { ttable, charsets })
+      },
+      Some((&CHARSET_ACCEPTED, name)) => Ok(Charset::Accepted(charset_name(name)?)),
+      Some((&CHARSET_REJECTED, &[])) => Ok(Charset::Rejected),
+      Some((&CHARSET_TTABLE_IS, table)) => Ok(Charset::TtableIs(TranslationTable::parse(table)?)),
+      Some((&CHARSET_TTABLE_REJECTED, &[])) => Ok(Charset::TtableRejected),
+      Some((&CHARSET_TTABLE_ACK, &[])) => Ok(Charset::TtableAck),
+      Some((&CHARSET_TTABLE_NAK, &[])) => Ok(Charset::TtableNak),
+      _ => Err(TelnetError::InvalidSubnegotiationSequence),
+    }
+  }
+
+  pub fn payload(&self) -> Vec<u8> {
+    match self {
+      Charset::Request { ttable, charsets } => {
+        let mut payload = vec![CHARSET_REQUEST];
+        if let Some(version) = ttable {
+          payload.extend_from_slice(TTABLE_MARKER);
+          payload.push(*version);
+        }
+        for charset in charsets {
+          payload.push(CHARSET_SEPARATOR);
+          payload.extend_from_slice(charset.as_bytes());
+        }
+        payload
+      },
+      Charset::Accepted(charset) => {
+        let mut payload = vec![CHARSET_ACCEPTED];
+        payload.extend_from_slice(charset.as_bytes());
+        payload
+      },
+      Charset::Rejected => vec![CHARSET_REJECTED],
+      Charset::TtableIs(table) => {
+        let mut payload = vec![CHARSET_TTABLE_IS];
+        payload.extend(table.payload());
+        payload
+      },
+      Charset::TtableRejected => vec![CHARSET_TTABLE_REJECTED],
+      Charset::TtableAck => vec![CHARSET_TTABLE_ACK],
+      Charset::TtableNak => vec![CHARSET_TTABLE_NAK],
+    }
+  }
+}
+
+fn charset_name(bytes: &[u8]) -> Result<String, TelnetError> {
+  match String::from_utf8(bytes.to_vec()) {
+    Ok(name) if !name.is_empty() => Ok(name),
+    _ => Err(TelnetError::InvalidSubnegotiationSequence),
+  }
+}
+
+// one direction of a TTABLE-IS, count characters of size bits each
+#[derive(Debug,PartialEq)]
+pub struct TranslationMap {
+  pub charset: String,
+  pub size: u8,
+  pub count: u32,
+  pub map: Vec<u8>,
+}
+
+impl TranslationMap {
+  // bytes needed for the map itself, each character takes a whole number of bytes
+  fn map_len(&self) -> usize {
+    self.count as usize * (self.size as usize).div_ceil(8)
+  }
+
+  // <charset> <sep> <size> <count>, the map follows both headers
+  fn parse_header(bytes: &[u8], separator: u8) -> Result<(TranslationMap, &[u8]), TelnetError> {
+    let end = bytes.iter().position(|byte| *byte == separator).ok_or(TelnetError::InvalidSubnegotiationSequence)?;
+    let charset = charset_name(&bytes[..end])?;
+    let header = &bytes[end + 1..];
+    if header.len() < 4 {
+      return Err(TelnetError::InvalidSubnegotiationSequence);
+    }
+    // the count is 24 bits, big endian
+    let count = ((header[1] as u32) << 16) | ((header[2] as u32) << 8) | (header[3] as u32);
+    Ok((TranslationMap { charset, size: header[0], count, map: Vec::new() }, &header[4..]))
+  }
+
+  fn put_header(&self, payload: &mut Vec<u8>) {
+    payload.extend_from_slice(self.charset.as_bytes());
+    payload.push(CHARSET_SEPARATOR);
+    payload.push(self.size);
+    payload.extend_from_slice(&self.count.to_be_bytes()[1..]);
+  }
+}
+
+// TTABLE-IS <version> <sep> <charset1> <sep> <size1> <count1> <charset2> <sep> <size2> <count2>
+// <map1> <map2>. The first map converts the first charset into the second, the second map
+// converts back.
+#[derive(Debug,PartialEq)]
+pub struct TranslationTable {
+  pub version: u8,
+  pub first: TranslationMap,
+  pub second: TranslationMap,
+}
+
+impl TranslationTable {
+  pub fn parse(bytes: &[u8]) -> Result<TranslationTable, TelnetError> {
+    if bytes.len() < 2 {
+      return Err(TelnetError::InvalidSubnegotiationSequence);
+    }
+    let (version, separator) = (bytes[0], bytes[1]);
+    let (mut first, rest) = TranslationMap::parse_header(&bytes[2..], separator)?;
+    let (mut second, rest) = TranslationMap::parse_header(rest, separator)?;
+    if rest.len() != first.map_len() + second.map_len() {
+      return Err(TelnetError::InvalidSubnegotiationSequence);
+    }
+    let (map1, map2) = rest.split_at(first.map_len());
+    first.map = map1.to_vec();
+    second.map = map2.to_vec();
+    Ok(TranslationTable { version, first, second })
+  }
+
+  pub fn payload(&self) -> Vec<u8> {
+    let mut payload = vec![self.version, CHARSET_SEPARATOR];
+    self.first.put_header(&mut payload);
+    self.second.put_header(&mut payload);
+    payload.extend_from_slice(&self.first.map);
+    payload.extend_from_slice(&self.second.map);
+    payload
+  }
+}
+
+// RFC 2217 commands. A value of 0 asks for the current setting, the meaning of the other
+// values is defined per command in the RFC.
+#[derive(Debug,PartialEq)]
+pub enum ComPortCommand {
+  Signature(String),
+  SetBaudRate(u32),
+  SetDataSize(u8),
+  SetParity(u8),
+  SetStopSize(u8),
+  SetControl(u8),
+  NotifyLineState(u8),
+  NotifyModemState(u8),
+  FlowControlSuspend,
+  FlowControlResume,
+  SetLineStateMask(u8),
+  SetModemStateMask(u8),
+  PurgeData(u8),
+}
+
+impl ComPortCommand {
+  fn parse(command: u8, bytes: &[u8]) -> Result<ComPortCommand, TelnetError> {
+    match (command, bytes) {
+      (COM_PORT_SIGNATURE, text) => Ok(ComPortCommand::Signature(String::from_utf8_lossy(text).to_string())),
+      (COM_PORT_SET_BAUDRATE, &[a, b, c, d]) => Ok(ComPortCommand::SetBaudRate(u32::from_be_bytes([a, b, c, d]))),
+      (COM_PORT_SET_DATASIZE, &[value]) => Ok(ComPortCommand::SetDataSize(value)),
+      (COM_PORT_SET_PARITY, &[value]) => Ok(ComPortCommand::SetParity(value)),
+      (COM_PORT_SET_STOPSIZE, &[value]) => Ok(ComPortCommand::SetStopSize(value)),
+      (COM_PORT_SET_CONTROL, &[value]) => Ok(ComPortCommand::SetControl(value)),
+      (COM_PORT_NOTIFY_LINESTATE, &[value]) => Ok(ComPortCommand::NotifyLineState(value)),
+      (COM_PORT_NOTIFY_MODEMSTATE, &[value]) => Ok(ComPortCommand::NotifyModemState(value)),
+      (COM_PORT_FLOWCONTROL_SUSPEND, &[]) => Ok(ComPortCommand::FlowControlSuspend),
+      (COM_PORT_FLOWCONTROL_RESUME, &[]) => Ok(ComPortCommand::FlowControlResume),
+      (COM_PORT_SET_LINESTATE_MASK, &[value]) => Ok(ComPortCommand::SetLineStateMask(value)),
+      (COM_PORT_SET_MODEMSTATE_MASK, &[value]) => Ok(ComPortCommand::SetModemStateMask(value)),
+      (COM_PORT_PURGE_DATA, &[value]) => Ok(ComPortCommand::PurgeData(value)),
+      _ => Err(TelnetError::InvalidSubnegotiationSequence),
+    }
+  }
+
+  fn command(&self) -> u8 {
+    match self {
+      ComPortCommand::Signature(_) => COM_PORT_SIGNATURE,
+      ComPortCommand::SetBaudRate(_) => COM_PORT_SET_BAUDRATE,
+      ComPortCommand::SetDataSize(_) => COM_PORT_SET_DATASIZE,
+      ComPortCommand::SetParity(_) => COM_PORT_SET_PARITY,
+      ComPortCommand::SetStopSize(_) => COM_PORT_SET_STOPSIZE,
+      ComPortCommand::SetControl(_) => COM_PORT_SET_CONTROL,
+      ComPortCommand::NotifyLineState(_) => COM_PORT_NOTIFY_LINESTATE,
+      ComPortCommand::NotifyModemState(_) => COM_PORT_NOTIFY_MODEMSTATE,
+      ComPortCommand::FlowControlSuspend => COM_PORT_FLOWCONTROL_SUSPEND,
+      ComPortCommand::FlowControlResume => COM_PORT_FLOWCONTROL_RESUME,
+      ComPortCommand::SetLineStateMask(_) => COM_PORT_SET_LINESTATE_MASK,
+      ComPortCommand::SetModemStateMask(_) => COM_PORT_SET_MODEMSTATE_MASK,
+      ComPortCommand::PurgeData(_) => COM_PORT_PURGE_DATA,
+    }
+  }
+
+  fn put_value(&self, payload: &mut Vec<u8>) {
+    match self {
+      ComPortCommand::Signature(text) => payload.extend_from_slice(text.as_bytes()),
+      ComPortCommand::SetBaudRate(rate) => payload.extend_from_slice(&rate.to_be_bytes()),
+      ComPortCommand::SetDataSize(value)
+      | ComPortCommand::SetParity(value)
+      | ComPortCommand::SetStopSize(value)
+      | ComPortCommand::SetControl(value)
+      | ComPortCommand::NotifyLineState(value)
+      | ComPortCommand::NotifyModemState(value)
+      | ComPortCommand::SetLineStateMask(value)
+      | ComPortCommand::SetModemStateMask(value)
+      | ComPortCommand::PurgeData(value) => payload.push(*value),
+      ComPortCommand::FlowControlSuspend | ComPortCommand::FlowControlResume => {},
+    }
+  }
+}
+
+// commands sent by the client, and the server's commands and replies which are numbered 100
+// higher
+#[derive(Debug,PartialEq)]
+pub enum ComPortControl {
+  Client(ComPortCommand),
+  Server(ComPortCommand),
+}
+
+impl ComPortControl {
+  pub fn parse(bytes: &[u8]) -> Result<ComPortControl, TelnetError> {
+    match bytes.split_first() {
+      Some((&command, value)) if command >= COM_PORT_SERVER_OFFSET => {
+        Ok(ComPortControl::Server(ComPortCommand::parse(command - COM_PORT_SERVER_OFFSET, value)?))
+      },
+      Some((&command, value)) => Ok(ComPortControl::Client(ComPortCommand::parse(command, value)?)),
+      None => Err(TelnetError::InvalidSubnegotiationSequence),
+    }
+  }
+
+  pub fn payload(&self) -> Vec<u8> {
+    let (command, offset) = match self {
+      ComPortControl::Client(command) => (command, 0),
+      ComPortControl::Server(command) => (command, COM_PORT_SERVER_OFFSET),
+    };
+    let mut payload = vec![command.command() + offset];
+    command.put_value(&mut payload);
+    payload
+  }
+}
+
+// an authentication type and its modifier bits, which say who authenticates whom, one way
+// or mutually, and how encryption is started
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct AuthType {
+  pub kind: u8,
+  pub modifier: u8,
+}
+
+impl AuthType {
+  pub fn new(kind: u8, modifier: u8) -> AuthType {
+    AuthType { kind, modifier }
+  }
+}
+
+// RFC 2941. The client sends IS and the server answers with REPLY, the meaning of the data
+// depends on the authentication type.
+#[derive(Debug,PartialEq)]
+pub enum Authentication {
+  Is(AuthType, Vec<u8>),
+  // the types the server accepts in order of preference
+  Send(Vec<AuthType>),
+  Reply(AuthType, Vec<u8>),
+  // the name the client wants to log in as
+  Name(String),
+}
+
+impl Authentication {
+  pub fn parse(bytes: &[u8]) -> Result<Authentication, TelnetError> {
+    match bytes {
+      [IS, kind, modifier, data @ ..] => Ok(Authentication::Is(AuthType::new(*kind, *modifier), data.to_vec())),
+      [SEND, pairs @ ..] if !pairs.is_empty() && pairs.len() % 2 == 0 => {
+        Ok(Authentication::Send(pairs.chunks(2).map(|pair| AuthType::new(pair[0], pair[1])).collect()))
+      },
+      [AUTH_REPLY, kind, modifier, data @ ..] => Ok(Authentication::Reply(AuthType::new(*kind, *modifier), data.to_vec())),
+      [AUTH_NAME, name @ ..] => Ok(Authentication::Name(String::from_utf8_lossy(name).to_string())),
+      _ => Err(TelnetError::InvalidSubnegotiationSequence),
+    }
+  }
+
+  pub fn payload(&self) -> Vec<u8> {
+    match self {
+      Authentication::Is(auth_type, data) => {
+        let mut payload = vec![IS, auth_type.kind, auth_type.modifier];
+        payload.extend_from_slice(data);
+        payload
+      },
+      Authentication::Send(auth_types) => {
+        let mut payload = vec![SEND];
+        for auth_type in auth_types {
+          payload.push(auth_type.kind);
+          payload.push(auth_type.modifier);
+        }
+        payload
+      },
+      Authentication::Reply(auth_type, data) => {
+        let mut payload = vec![AUTH_REPLY, auth_type.kind, auth_type.modifier];
+        payload.extend_from_slice(data);
+        payload
+      },
+      Authentication::Name(name) => {
+        let mut payload = vec![AUTH_NAME];
+        payload.extend_from_slice(name.as_bytes());
+        payload
+      },
+    }
+  }
+}
+
+// RFC 2946. Everything a side sends after its START, up to and including its END, is
+// encrypted.
+#[derive(Debug,PartialEq)]
+pub enum Encrypt {
+  // the encryption types the sender supports in order of preference
+  Support(Vec<u8>),
+  Is(u8, Vec<u8>),
+  Reply(u8, Vec<u8>),
+  Start(Vec<u8>),
+  End,
+  RequestStart(Vec<u8>),
+  RequestEnd,
+  EncKeyId(Vec<u8>),
+  DecKeyId(Vec<u8>),
+}
+
+impl Encrypt {
+  pub fn parse(bytes: &[u8]) -> Result<Encrypt, TelnetError> {
+    match bytes {
+      [ENCRYPT_SUPPORT, types @ ..] if !types.is_empty() => Ok(Encrypt::Support(types.to_vec())),
+      [IS, kind, data @ ..] => Ok(Encrypt::Is(*kind, data.to_vec())),
+      [ENCRYPT_REPLY, kind, data @ ..] => Ok(Encrypt::Reply(*kind, data.to_vec())),
+      [ENCRYPT_START, keyid @ ..] => Ok(Encrypt::Start(keyid.to_vec())),
+      [ENCRYPT_END] => Ok(Encrypt::End),
+      [ENCRYPT_REQUEST_START, keyid @ ..] => Ok(Encrypt::RequestStart(keyid.to_vec())),
+      [ENCRYPT_REQUEST_END] => Ok(Encrypt::RequestEnd),
+      [ENCRYPT_ENC_KEYID, keyid @ ..] => Ok(Encrypt::EncKeyId(keyid.to_vec())),
+      [ENCRYPT_DEC_KEYID, keyid @ ..] => Ok(Encrypt::DecKeyId(keyid.to_vec())),
+      _ => Err(TelnetError::InvalidSubnegotiationSequence),
+    }
+  }
+
+  pub fn payload(&self) -> Vec<u8> {
+    let (command, data): (&[u8], &[u8]) = match self {
+      Encrypt::Support(types) => (&[ENCRYPT_SUPPORT], types),
+      Encrypt::Is(kind, data) => (&[IS, *kind], data),
+      Encrypt::Reply(kind, data) => (&[ENCRYPT_REPLY, *kind], data),
+      Encrypt::Start(keyid) => (&[ENCRYPT_START], keyid),
+      Encrypt::End => (&[ENCRYPT_END], &[]),
+      Encrypt::RequestStart(keyid) => (&[ENCRYPT_REQUEST_START], keyid),
+      Encrypt::RequestEnd => (&[ENCRYPT_REQUEST_END], &[]),
+      Encrypt::EncKeyId(keyid) => (&[ENCRYPT_ENC_KEYID], keyid),
+      Encrypt::DecKeyId(keyid) => (&[ENCRYPT_DEC_KEYID], keyid),
+    };
+    let mut payload = command.to_vec();
+    payload.extend_from_slice(data);
+    payload
+  }
+}
+
+// the session a client asks for along with its device type, by LU name or by the printer
+// associated with a terminal LU
+#[derive(Debug,PartialEq)]
+pub enum DeviceResource {
+  Connect(String),
+  Associate(String),
+}
+
+// RFC 2355
+#[derive(Debug,PartialEq)]
+pub enum Tn3270e {
+  SendDeviceType,
+  DeviceTypeRequest { device_type: String, resource: Option<DeviceResource> },
+  DeviceTypeIs { device_type: String, device_name: String },
+  DeviceTypeReject(u8),
+  FunctionsRequest(Vec<u8>),
+  FunctionsIs(Vec<u8>),
+}
+
+impl Tn3270e {
+  pub fn parse(bytes: &[u8]) -> Result<Tn3270e, TelnetError> {
+    match bytes {
+      [TN3270E_SEND, TN3270E_DEVICE_TYPE] => Ok(Tn3270e::SendDeviceType),
+      [TN3270E_DEVICE_TYPE, TN3270E_REQUEST, rest @ ..] => {
+        // device types are ASCII, so the first CONNECT or ASSOCIATE byte ends the name
+        match rest.iter().position(|byte| *byte == TN3270E_CONNECT || *byte == TN3270E_ASSOCIATE) {
+          Some(end) => {
+            let name = tn3270e_name(&rest[end + 1..])?;
+            let resource = match rest[end] {
+              TN3270E_CONNECT => DeviceResource::Connect(name),
+              _ => DeviceResource::Associate(name),
+            };
+            Ok(Tn3270e::DeviceTypeRequest { device_type: tn3270e_name(&rest[..end])?, resource: Some(resource) })
+          },
+          None => Ok(Tn3270e::DeviceTypeRequest { device_type: tn3270e_name(rest)?, resource: None }),
+        }
+      },
+      [TN3270E_DEVICE_TYPE, TN3270E_IS, rest @ ..] => {
+        let end = rest.iter().position(|byte| *byte == TN3270E_CONNECT).ok_or(TelnetError::InvalidSubnegotiationSequence)?;
+        Ok(Tn3270e::DeviceTypeIs { device_type: tn3270e_name(&rest[..end])?, device_name: tn3270e_name(&rest[end + 1..])? })
+      },
+      [TN3270E_DEVICE_TYPE, TN3270E_REJECT, TN3270E_REASON, reason] => Ok(Tn3270e::DeviceTypeReject(*reason)),
+      [TN3270E_FUNCTIONS, TN3270E_REQUEST, functions @ ..] => Ok(Tn3270e::FunctionsRequest(functions.to_vec())),
+      [TN3270E_FUNCTIONS, TN3270E_IS, functions @ ..] => Ok(Tn3270e::FunctionsIs(functions.to_vec())),
+      _ => Err(TelnetError::InvalidSubnegotiationSequence),
+    }
+  }
+
+  pub fn payload(&self) -> Vec<u8> {
+    match self {
+      Tn3270e::SendDeviceType => vec![TN3270E_SEND, TN3270E_DEVICE_TYPE],
+      Tn3270e::DeviceTypeRequest { device_type, resource } => {
+        let mut payload = vec![TN3270E_DEVICE_TYPE, TN3270E_REQUEST];
+        payload.extend_from_slice(device_type.as_bytes());
+        match resource {
+          Some(DeviceResource::Connect(name)) => {
+            payload.push(TN3270E_CONNECT);
+            payload.extend_from_slice(name.as_bytes());
+          },
+          Some(DeviceResource::Associate(name)) => {
+            payload.push(TN3270E_ASSOCIATE);
+            payload.extend_from_slice(name.as_bytes());
+          },
+          None => {},
+        }
+        payload
+      },
+      Tn3270e::DeviceTypeIs { device_type, device_name } => {
+        let mut payload = vec![TN3270E_DEVICE_TYPE, TN3270E_IS];
+        payload.extend_from_slice(device_type.as_bytes());
+        payload.push(TN3270E_CONNECT);
+        payload.extend_from_slice(device_name.as_bytes());
+        payload
+      },
+      Tn3270e::DeviceTypeReject(reason) => vec![TN3270E_DEVICE_TYPE, TN3270E_REJECT, TN3270E_REASON, *reason],
+      Tn3270e::FunctionsRequest(functions) => {
+        let mut payload = vec![TN3270E_FUNCTIONS, TN3270E_REQUEST];
+        payload.extend_from_slice(functions);
+        payload
+      },
+      Tn3270e::FunctionsIs(functions) => {
+        let mut payload = vec![TN3270E_FUNCTIONS, TN3270E_IS];
+        payload.extend_from_slice(functions);
+        payload
+      },
+    }
+  }
+
+  // RFC 2355 FUNCTIONS negotiation. A request we fully support is confirmed with IS, otherwise
+  // we counter with a REQUEST for the functions we do support.
+  pub fn answer_functions(requested: &[u8], supported: &[u8]) -> Tn3270e {
+    let common: Vec<u8> = requested.iter().cloned().filter(|function| supported.contains(function)).collect();
+    if common.len() == requested.len() {
+      Tn3270e::FunctionsIs(common)
+    } else {
+      Tn3270e::FunctionsRequest(common)
+    }
+  }
+}
+
+fn tn3270e_name(bytes: &[u8]) -> Result<String, TelnetError> {
+  match std::str::from_utf8(bytes) {
+    Ok(name) if !name.is_empty() && name.is_ascii() => Ok(name.to_string()),
+    _ => Err(TelnetError::InvalidSubnegotiationSequence),
+  }
+}
@@ -0,0 +1,138 @@
+use crate::event::*;
+use crate::subnegotiation::*;
+
+// stop asking clients that never repeat themselves
+pub const MAX_TERMINAL_TYPES: usize = 32;
+
+// Collects every terminal type a client supports. RFC 1091 clients send their last type
+// twice at the end of the list, some clients wrap around to the first type instead.
+#[derive(Debug, Default)]
+pub struct TerminalTypeCycle {
+  names: Vec<String>,
+  complete: bool,
+}
+
+impl TerminalTypeCycle {
+  pub fn new() -> TerminalTypeCycle {
+    TerminalTypeCycle::default()
+  }
+
+  // IAC SB TERMINAL-TYPE SEND IAC SE
+  pub fn request() -> TelnetEvent {
+    TelnetEvent::Subnegotiation(SubnegotiationType::TerminalType(TerminalType::Send))
+  }
+
+  // record the answer to the last SEND, returns the next SEND until the list is complete
+  pub fn receive(&mut self, name: String) -> Option<TelnetEvent> {
+    if self.complete {
+      return None;
+    }
+
+    let repeated = match (self.names.first(), self.names.last()) {
+      (Some(first), Some(last)) => first.eq_ignore_ascii_case(&name) || last.eq_ignore_ascii_case(&name),
+      _ => false,
+    };
+    if !repeated {
+      self.names.push(name);
+    }
+
+    if repeated || self.names.len() >= MAX_TERMINAL_TYPES {
+      self.complete = true;
+      None
+    } else {
+      Some(TerminalTypeCycle::request())
+    }
+  }
+
+  pub fn is_complete(&self) -> bool {
+    self.complete
+  }
+
+  // the terminal types in the order the client prefers them
+  pub fn names(&self) -> &[String] {
+    &self.names
+  }
+
+  pub fn into_names(self) -> Vec<String> {
+    self.names
+  }
+}
+
+// Mud Terminal Type Standard, sent as "MTTS <bitvector>" in the third TERMINAL-TYPE IS
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub struct MttsFlags {
+  pub ansi: bool,
+  pub vt100: bool,
+  pub utf8: bool,
+  pub colors_256: bool,
+  pub mouse_tracking: bool,
+  pub osc_color_palette: bool,
+  pub screen_reader: bool,
+  pub proxy: bool,
+  pub truecolor: bool,
+  pub mnes: bool,
+  pub mslp: bool,
+  pub ssl: bool,
+}
+
+impl MttsFlags {
+  pub fn from_bits(bits: u32) -> MttsFlags {
+    MttsFlags {
+      ansi: bits & 1 != 0,
+      vt100: bits & 2 != 0,
+      utf8: bits & 4 != 0,
+      colors_256: bits & 8 != 0,
+      mouse_tracking: bits & 16 != 0,
+      osc_color_palette: bits & 32 != 0,
+      screen_reader: bits & 64 != 0,
+      proxy: bits & 128 != 0,
+      truecolor: bits & 256 != 0,
+      mnes: bits & 512 != 0,
+      mslp: bits & 1024 != 0,
+      ssl: bits & 2048 != 0,
+    }
+  }
+
+  pub fn bits(&self) -> u32 {
+    let flags = [
+      self.ansi,
+      self.vt100,
+      self.utf8,
+      self.colors_256,
+      self.mouse_tracking,
+      self.osc_color_palette,
+      self.screen_reader,
+      self.proxy,
+      self.truecolor,
+      self.mnes,
+      self.mslp,
+      self.ssl,
+    ];
+    flags.iter()
+      .enumerate()
+      .filter(|(_, set)| **set)
+      .fold(0, |bits, (bit, _)| bits | 1 << bit)
+  }
+
+  // parse a terminal type of the form "MTTS 137"
+  pub fn parse(name: &str) -> Option<MttsFlags> {
+    let name = name.trim();
+    match (name.get(..5), name.get(5..)) {
+      (Some(prefix), Some(bits)) if prefix.eq_ignore_ascii_case("MTTS ") => {
+        bits.trim().parse().ok().map(MttsFlags::from_bits)
+      },
+      _ => None,
+    }
+  }
+
+  // the first MTTS entry in a list of terminal types
+  pub fn find(names: &[String]) -> Option<MttsFlags> {
+    names.iter().filter_map(|name| MttsFlags::parse(name)).next()
+  }
+}
+
+impl TerminalTypeCycle {
+  pub fn mtts(&self) -> Option<MttsFlags> {
+    MttsFlags::find(&self.names)
+  }
+}
@@ -0,0 +1,96 @@
+use crate::consts::*;
+use crate::error::*;
+
+// RFC 2355, what the data in a TN3270E record is
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Tn3270eDataType {
+  Data3270,
+  ScsData,
+  Response,
+  BindImage,
+  Unbind,
+  NvtData,
+  Request,
+  SscpLuData,
+  PrintEoj,
+  Other(u8),
+}
+
+impl From<u8> for Tn3270eDataType {
+  fn from(byte: u8) -> Tn3270eDataType {
+    match byte {
+      TN3270E_DT_3270_DATA => Tn3270eDataType::Data3270,
+      TN3270E_DT_SCS_DATA => Tn3270eDataType::ScsData,
+      TN3270E_DT_RESPONSE => Tn3270eDataType::Response,
+      TN3270E_DT_BIND_IMAGE => Tn3270eDataType::BindImage,
+      TN3270E_DT_UNBIND => Tn3270eDataType::Unbind,
+      TN3270E_DT_NVT_DATA => Tn3270eDataType::NvtData,
+      TN3270E_DT_REQUEST => Tn3270eDataType::Request,
+      TN3270E_DT_SSCP_LU_DATA => Tn3270eDataType::SscpLuData,
+      TN3270E_DT_PRINT_EOJ => Tn3270eDataType::PrintEoj,
+      _ => Tn3270eDataType::Other(byte),
+    }
+  }
+}
+
+impl From<Tn3270eDataType> for u8 {
+  fn from(data_type: Tn3270eDataType) -> u8 {
+    match data_type {
+      Tn3270eDataType::Data3270 => TN3270E_DT_3270_DATA,
+      Tn3270eDataType::ScsData => TN3270E_DT_SCS_DATA,
+      Tn3270eDataType::Response => TN3270E_DT_RESPONSE,
+      Tn3270eDataType::BindImage => TN3270E_DT_BIND_IMAGE,
+      Tn3270eDataType::Unbind => TN3270E_DT_UNBIND,
+      Tn3270eDataType::NvtData => TN3270E_DT_NVT_DATA,
+      Tn3270eDataType::Request => TN3270E_DT_REQUEST,
+      Tn3270eDataType::SscpLuData => TN3270E_DT_SSCP_LU_DATA,
+      Tn3270eDataType::PrintEoj => TN3270E_DT_PRINT_EOJ,
+      Tn3270eDataType::Other(byte) => byte,
+    }
+  }
+}
+
+// a record sent once TN3270E is in effect, the 5 byte header followed by the data up to
+// IAC EOR
+#[derive(Debug, PartialEq, Clone)]
+pub struct Tn3270eRecord {
+  pub data_type: Tn3270eDataType,
+  pub request_flag: u8,
+  pub response_flag: u8,
+  pub seq_number: u16,
+  pub payload: Vec<u8>,
+}
+
+impl Tn3270eRecord {
+  // a record with both flags and the sequence number cleared
+  pub fn new(data_type: Tn3270eDataType, payload: Vec<u8>) -> Tn3270eRecord {
+    Tn3270eRecord {
+      data_type,
+      request_flag: TN3270E_RQF_ERR_COND_CLEARED,
+      response_flag: TN3270E_RSF_NO_RESPONSE,
+      seq_number: 0,
+      payload,
+    }
+  }
+
+  // the unescaped bytes between the previous and the next IAC EOR
+  pub fn parse(bytes: &[u8]) -> Result<Tn3270eRecord, TelnetError> {
+    match bytes {
+      [data_type, request_flag, response_flag, seq_high, seq_low, payload @ ..] => Ok(Tn3270eRecord {
+        data_type: (*data_type).into(),
+        request_flag: *request_flag,
+        response_flag: *response_flag,
+        seq_number: u16::from_be_bytes([*seq_high, *seq_low]),
+        payload: payload.to_vec(),
+      }),
+      _ => Err(TelnetError::InvalidRecordHeader),
+    }
+  }
+
+  pub fn bytes(&self) -> Vec<u8> {
+    let mut bytes = vec![self.data_type.into(), self.request_flag, self.response_flag];
+    bytes.extend_from_slice(&self.seq_number.to_be_bytes());
+    bytes.extend_from_slice(&self.payload);
+    bytes
+  }
+}